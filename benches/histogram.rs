@@ -0,0 +1,68 @@
+//! Throughput of counting bytes, from small chunks to whole blocks of a file.
+//!
+//! Run with `cargo bench --bench histogram`; `baseline` is the former byte-by-byte loop with an
+//! overflow check per byte.
+
+use std::io::Write;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tropy::{Calculator, Histogram};
+
+const SIZES: [usize; 5] = [64, 1024, 16 << 10, 256 << 10, 1 << 20];
+
+fn input(len: usize) -> Vec<u8> {
+    // compressed-looking data with a few runs, which are the worst case for a single table
+    (0u32..len as u32)
+        .map(|i| {
+            if i % 64 < 8 {
+                0
+            } else {
+                (i.wrapping_mul(2654435761) >> 13) as u8
+            }
+        })
+        .collect()
+}
+
+fn baseline(counts: &mut [u64; 256], input: &[u8]) {
+    input.iter().for_each(|byte| {
+        counts[*byte as usize] = counts[*byte as usize]
+            .checked_add(1)
+            .expect("Count exceeded the length of a u64")
+    });
+}
+
+fn counting(c: &mut Criterion) {
+    let mut group = c.benchmark_group("count");
+    for &len in SIZES.iter() {
+        let data = input(len);
+        group.throughput(Throughput::Bytes(len as u64));
+        group.bench_with_input(BenchmarkId::new("baseline", len), &data, |b, data| {
+            let mut counts = [0u64; 256];
+            b.iter(|| baseline(&mut counts, black_box(data)))
+        });
+        group.bench_with_input(BenchmarkId::new("histogram", len), &data, |b, data| {
+            let mut h = Histogram::new();
+            b.iter(|| h.update(black_box(data)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn entropy(c: &mut Criterion) {
+    let mut group = c.benchmark_group("entropy");
+    for &len in SIZES.iter() {
+        let data = input(len);
+        group.throughput(Throughput::Bytes(len as u64));
+        group.bench_with_input(BenchmarkId::new("calculator", len), &data, |b, data| {
+            let mut calc = Calculator::new();
+            b.iter(|| {
+                calc.write_all(black_box(data)).unwrap();
+                calc.entropy()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, counting, entropy);
+criterion_main!(benches);
@@ -1,568 +1,2122 @@
 extern crate structopt;
 
+use sha2::{Digest, Sha256};
 use structopt::StructOpt;
 
-use std::error::Error;
+use std::collections::VecDeque;
 use std::fs::File;
 use std::io;
-use std::io::{BufRead, BufReader, Read, Write};
+use std::io::Read;
+use std::io::{BufRead, BufReader, ErrorKind, Seek, SeekFrom};
 use std::process::exit;
-use tropy::colour::{Hsl, Rgb};
-use tropy::Calculator;
-use ansi_rgb::{ Foreground, Background };
-use rgb::RGB8;
+use std::str::FromStr;
+use std::thread;
+use tropy::{
+    differences, segment, slice_lz_complexity, ChunkRecord, Class, Elf, ElfReport, EntCalculator,
+    EntReport, Error, Estimator, Histogram, Hit, Input, MarkovCalculator, MarkovReport, Metric,
+    ParallelScan, Profile, Region, RollingCalculator, RuleSet, ScanReport, SignatureScanner,
+    Symbol, SymbolCalculator, Uniformity, UniformityTest, DEFAULT_PENALTY,
+};
+
+/// Listed in the help, keep in sync with [exit_code]
+const EXIT_CODES: &str = "EXIT CODES:
+    0    Success
+    1    Invalid arguments
+    2    Unsupported format, e.g. of a reference histogram
+    3    Reading the input failed
+    4    A counter overflowed";
+
+/// The help of --rules, ending in an example rule
+const RULES_HELP: &str =
+    "Look for user-defined signatures from this rule file as well, implies --signatures.
+One rule per line: a name, the pattern in hex with ?? for any byte, and options, e.g.
+acme-fw 41 43 4d 45 ?? ?? 01&0f offset=0 at=0x200 entropy>=7.5/4k";
 
 /// Read bytes from file or stdin and calculate the Shannon entropy for for chunks of a fixed size.
-/// Then display it colour-coded in the terminal or write it to stdout as csv.
+/// Then display it colour-coded in the terminal or write it to stdout as csv or json.
 #[derive(Debug, StructOpt)]
+#[structopt(raw(after_help = "EXIT_CODES"))]
 struct Tropy {
     #[structopt(
         name = "input",
-        help = "File to be read for input or \'-\' to use open stdin"
+        help = "File to be read for input or \'-\' to use open stdin (required without a subcommand)"
     )]
-    file: String,
+    file: Option<String>,
     #[structopt(
         long = "bytes",
         default_value = "1024",
         help = "The number of bytes to be read for each entropy calculation"
     )]
     bytes: u32,
+    #[structopt(
+        long = "window",
+        help = "Calculate the entropy over a window of this many bytes sliding over the input instead of disjoint chunks"
+    )]
+    window: Option<u32>,
+    #[structopt(
+        long = "step",
+        default_value = "1",
+        help = "The number of bytes the sliding window advances between two entropy values (requires --window)"
+    )]
+    step: u32,
+    #[structopt(
+        long = "metric",
+        default_value = "shannon",
+        help = "The entropy measure used for colouring and csv output: shannon, min, collision or renyi:<order>"
+    )]
+    metric: Metric,
+    #[structopt(
+        long = "estimator",
+        default_value = "plugin",
+        help = "How the Shannon entropy is estimated: plugin, miller-madow, chao-shen, jackknife or normalised.\nThe plain plugin estimate can't reach 8 bits for small --bytes, the others correct for that"
+    )]
+    estimator: Estimator,
+    #[structopt(
+        long = "symbol",
+        default_value = "byte",
+        help = "The symbols the entropy is calculated over: bit, nibble, byte, u16le, u16be, u32le or u32be.\nThe result is scaled to bits per byte, so a chunk of n symbols reaches at most log2(n) * 8 / <symbol bits> bits per byte: 2 for u32 in chunks of 1024 bytes, where even random data looks like low entropy. Use larger --bytes for wide symbols"
+    )]
+    symbol: Symbol,
+    #[structopt(
+        long = "ent",
+        help = "Run the tests of ent (chi-square, mean, Monte Carlo pi, serial correlation) on every chunk and the whole input.\nAdds them as csv columns and prints a summary at the end"
+    )]
+    ent: bool,
+    #[structopt(
+        long = "markov",
+        help = "Calculate the conditional entropy given the preceding <order> bytes (1 to 3) for every chunk.\nChunks with a high entropy which are predictable from their context are drawn shaded and flagged in the csv output"
+    )]
+    markov: Option<usize>,
+    #[structopt(
+        long = "significance",
+        help = "Test every chunk against uniformly random bytes at this significance level, e.g. 0.01.\nChunks indistinguishable from random are marked with ▓ in the map; the csv output gains the confidence interval of the entropy, the p-value and a random flag"
+    )]
+    significance: Option<f64>,
+    #[structopt(
+        long = "lz",
+        help = "Estimate how well every chunk compresses with LZ77, in bits per byte.\nCatches repetition the byte histogram misses; the map is coloured by it instead of the entropy"
+    )]
+    lz: bool,
+    #[structopt(
+        long = "divergence",
+        help = "Colour every chunk by the Jensen-Shannon divergence (0 to 1 bit) of its byte histogram from the previous chunk ('previous') or from a reference histogram file.\nReference files hold the 256 counts as written by --histogram"
+    )]
+    divergence: Option<Reference>,
+    #[structopt(
+        long = "histogram",
+        help = "Print the byte histogram of the whole input in the format read by --divergence instead of the map"
+    )]
+    histogram: bool,
+    #[structopt(
+        long = "classify",
+        help = "Classify every chunk as zero padding, erased flash, text, code, compressed, random or other.\nThe map is coloured by class instead of the entropy"
+    )]
+    classify: bool,
+    #[structopt(
+        long = "signatures",
+        help = "Look for the headers of embedded files (compressed data, filesystems, executables, images, device trees, certificates) while scanning.\nHits are listed under their row of the map and added to the csv and json output"
+    )]
+    signatures: bool,
+    #[structopt(long = "rules", raw(help = "RULES_HELP"))]
+    rules: Option<String>,
+    #[structopt(
+        long = "elf",
+        help = "Read the input as an ELF binary: mark where its sections start below the rows of the map, add the section and segment of every chunk to the csv and json output and list the entropy of every section and segment, flagging packed or encrypted ones"
+    )]
+    elf: bool,
+    #[structopt(
+        long = "threads",
+        help = "The number of threads used to scan a file in chunks [default: number of CPUs]\nStdin and the sliding window are always read sequentially"
+    )]
+    threads: Option<usize>,
     #[structopt(
         long = "csv",
         help = "Output as csv to stdout instead of using color-coding on the terminal.\nFormats as: <startbyte>;<entropy>"
     )]
     csv: bool,
+    #[structopt(
+        long = "format",
+        help = "The output format: map (default), csv (same as --csv), json or ndjson.\njson prints the input, chunk size, chunks and summary as one document at the end, ndjson prints one line per chunk as soon as it is calculated"
+    )]
+    format: Option<Format>,
+    #[structopt(
+        long = "save-profile",
+        help = "Also save the value of every chunk with the input's name, size and SHA-256 to this file.\nDraw it again with 'tropy render <profile>'"
+    )]
+    save_profile: Option<String>,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Split the input into regions of similar entropy by change-point detection and list them
+    #[structopt(name = "regions", raw(after_help = "EXIT_CODES"))]
+    Regions {
+        #[structopt(
+            name = "input",
+            help = "File to be read for input or \'-\' to use open stdin"
+        )]
+        file: String,
+        #[structopt(
+            long = "bytes",
+            default_value = "1024",
+            help = "The number of bytes to be read for each entropy calculation"
+        )]
+        bytes: u32,
+        #[structopt(
+            long = "metric",
+            default_value = "shannon",
+            help = "The entropy measure regions are found by: shannon, min, collision or renyi:<order>"
+        )]
+        metric: Metric,
+        #[structopt(
+            long = "estimator",
+            default_value = "plugin",
+            help = "How the Shannon entropy is estimated: plugin, miller-madow, chao-shen, jackknife or normalised"
+        )]
+        estimator: Estimator,
+        #[structopt(
+            long = "penalty",
+            help = "The sensitivity: a boundary must reduce the squared deviation of the chunks from their region's mean by this many bits^2.\nLower values give more, shorter regions"
+        )]
+        penalty: Option<f64>,
+        #[structopt(
+            long = "threads",
+            help = "The number of threads used to scan a file [default: number of CPUs]"
+        )]
+        threads: Option<usize>,
+        #[structopt(
+            long = "csv",
+            help = "Output as csv to stdout.\nFormats as: <startbyte>;<endbyte>;<mean entropy>;<class>"
+        )]
+        csv: bool,
+    },
+    /// Draw the maps of two inputs interleaved row by row with their difference and list where they
+    /// diverge
+    #[structopt(name = "diff", raw(after_help = "EXIT_CODES"))]
+    Diff {
+        #[structopt(name = "a", help = "The first file, e.g. the older release")]
+        a: String,
+        #[structopt(name = "b", help = "The file compared with the first")]
+        b: String,
+        #[structopt(
+            long = "bytes",
+            default_value = "1024",
+            help = "The number of bytes to be read for each entropy calculation"
+        )]
+        bytes: u32,
+        #[structopt(
+            long = "threshold",
+            default_value = "0.5",
+            help = "The difference in bits per byte from which chunks count as diverging"
+        )]
+        threshold: f64,
+        #[structopt(
+            long = "threads",
+            help = "The number of threads used to scan a file [default: number of CPUs]"
+        )]
+        threads: Option<usize>,
+        #[structopt(
+            long = "csv",
+            help = "Output the chunks as csv to stdout instead of the maps and regions.\nFormats as: <startbyte>;<entropy a>;<entropy b>;<difference>"
+        )]
+        csv: bool,
+    },
+    /// Draw the map of a profile saved with --save-profile or of the csv output of tropy
+    #[structopt(name = "render", raw(after_help = "EXIT_CODES"))]
+    Render {
+        #[structopt(name = "profile", help = "The profile or csv file to draw")]
+        file: String,
+        #[structopt(
+            long = "save-profile",
+            help = "Save what was read as a profile to this file, e.g. to convert csv output"
+        )]
+        save_profile: Option<String>,
+    },
+}
+
+/// Number of blocks per row of the terminal map
+const COLUMNS: usize = 80;
+
+/// Exclusive upper bound of the scaled entropy and the coordinates in the 6x6x6 ANSI colour cube
+/// used to draw it, ordered from low to high entropy
+const PALETTE: [(u32, [u32; 3]); 38] = [
+    (255, [0, 0, 0]),
+    (260, [0, 0, 1]),
+    (265, [0, 0, 2]),
+    (270, [0, 0, 3]),
+    (272, [0, 0, 4]),
+    (274, [0, 0, 5]),
+    (276, [0, 1, 5]),
+    (278, [0, 1, 4]),
+    (280, [0, 1, 3]),
+    (285, [0, 2, 4]),
+    (290, [1, 4, 5]),
+    (292, [2, 5, 5]),
+    (294, [2, 4, 5]),
+    (296, [1, 2, 4]),
+    (298, [1, 2, 5]),
+    (300, [2, 2, 5]),
+    (305, [2, 1, 5]),
+    (310, [2, 0, 5]),
+    (312, [2, 0, 4]),
+    (314, [1, 0, 4]),
+    (316, [1, 0, 3]),
+    (318, [1, 0, 2]),
+    (320, [2, 0, 3]),
+    (322, [3, 0, 4]),
+    (324, [4, 0, 5]),
+    (326, [4, 0, 4]),
+    (328, [5, 0, 5]),
+    (330, [5, 0, 4]),
+    (334, [5, 0, 3]),
+    (340, [4, 0, 3]),
+    (342, [4, 0, 2]),
+    (344, [4, 0, 1]),
+    (346, [5, 0, 2]),
+    (350, [5, 0, 1]),
+    (355, [5, 0, 0]),
+    (360, [4, 0, 0]),
+    (365, [3, 0, 0]),
+    (u32::MAX, [2, 0, 0]),
+];
+
+/// How the results are written to stdout
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Map,
+    Csv,
+    Json,
+    Ndjson,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "map" => Ok(Format::Map),
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            "ndjson" => Ok(Format::Ndjson),
+            _ => Err(format!(
+                "Unknown format {:?}, expected map, csv, json or ndjson",
+                s
+            )),
+        }
+    }
+}
+
+/// What chunks are compared with for --divergence
+#[derive(Debug, Clone)]
+enum Reference {
+    Previous,
+    File(String),
+}
+
+impl FromStr for Reference {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "previous" => Ok(Reference::Previous),
+            path => Ok(Reference::File(path.to_string())),
+        }
+    }
+}
+
+/// The state of the comparison, updated with the chunks in offset order
+enum Divergence {
+    Previous(Option<Histogram>),
+    Fixed(Histogram),
+}
+
+impl Divergence {
+    fn next(&mut self, hist: Histogram) -> Option<f64> {
+        match self {
+            // the first chunk has nothing to compare with
+            Divergence::Previous(previous) => {
+                let d = previous.as_ref().map(|p| hist.js_divergence(p));
+                *previous = Some(hist);
+                d
+            }
+            Divergence::Fixed(reference) => Some(hist.js_divergence(reference)),
+        }
+    }
+}
+
+/// What is calculated for every chunk
+#[derive(Debug, Clone, Copy)]
+struct Measure {
+    metric: Metric,
+    estimator: Estimator,
+    symbol: Symbol,
+    ent: bool,
+    // order of the markov model
+    markov: Option<usize>,
+    uniformity: Option<UniformityTest>,
+    lz: bool,
+    // the divergence is calculated from the histograms in the sink
+    divergence: bool,
+    classify: bool,
+    // keep the histogram of every chunk
+    histogram: bool,
+    // keep the bytes of every chunk for the signature scan in the sink
+    bytes: bool,
+    // the section and segment are looked up in the sink
+    elf: bool,
+}
+
+/// The values calculated for one chunk
+struct Chunk {
+    // the selected metric
+    value: f64,
+    ent: Option<EntCalculator>,
+    markov: Option<MarkovReport>,
+    uniformity: Option<Uniformity>,
+    // estimated compressed size in bits per byte
+    lz: Option<f64>,
+    histogram: Option<Histogram>,
+    // filled in sequentially, as it may depend on the previous chunk
+    divergence: Option<f64>,
+    class: Option<Class>,
+    bytes: Option<Vec<u8>>,
+    // filled in sequentially once the scan has passed the end of the chunk
+    signatures: Option<Vec<Hit>>,
+    // the ELF section and segment of the first byte
+    section: Option<String>,
+    segment: Option<String>,
+}
+
+impl Chunk {
+    /// A chunk with nothing but the selected metric
+    fn of_value(value: f64) -> Chunk {
+        Chunk {
+            value,
+            ent: None,
+            markov: None,
+            uniformity: None,
+            lz: None,
+            histogram: None,
+            divergence: None,
+            class: None,
+            bytes: None,
+            signatures: None,
+            section: None,
+            segment: None,
+        }
+    }
+
+    /// The serialisable form for --format json and ndjson
+    fn record(&self, offset: u64, len: usize) -> ChunkRecord {
+        ChunkRecord {
+            ent: self.ent.as_ref().map(|ent| ent.report()),
+            markov: self.markov.clone(),
+            structured: self.markov.as_ref().map(|m| m.is_structured()),
+            uniformity: self.uniformity,
+            lz: self.lz,
+            divergence: self.divergence,
+            class: self.class,
+            signatures: self.signatures.clone(),
+            section: self.section.clone(),
+            segment: self.segment.clone(),
+            ..ChunkRecord::new(offset, len as u64, self.value)
+        }
+    }
+}
+
+impl Measure {
+    /// The name of what [Chunk::value] holds, saved in profiles
+    fn name(&self) -> String {
+        match (self.estimator, self.symbol) {
+            (Estimator::PlugIn, Symbol::Byte) => self.metric.to_string(),
+            (estimator, Symbol::Byte) => estimator.to_string(),
+            (_, symbol) => format!("{} of {}", self.metric, symbol),
+        }
+    }
+
+    /// The selected metric or estimate over the bytes of a histogram
+    fn value(&self, histogram: &Histogram) -> f64 {
+        match self.estimator {
+            Estimator::PlugIn => self.metric.of(histogram),
+            estimator => estimator.of(histogram),
+        }
+    }
+
+    fn chunk(&self, input: &[u8]) -> Result<Chunk, Error> {
+        let ent = if self.ent {
+            let mut ent = EntCalculator::new();
+            ent.update(input)?;
+            Some(ent)
+        } else {
+            None
+        };
+        let counted;
+        let histogram = match &ent {
+            Some(ent) => ent.histogram(),
+            None => {
+                counted = Histogram::from(input);
+                &counted
+            }
+        };
+        let value = match self.symbol {
+            Symbol::Byte => self.value(histogram),
+            symbol => {
+                // a dense table of u16 symbols would be larger than most chunks
+                let mut c = SymbolCalculator::with_capacity(symbol, input.len());
+                c.update(input);
+                c.measure(self.metric) * 8.0 / symbol.bits() as f64
+            }
+        };
+        let class = if self.classify {
+            Some(Class::of(histogram))
+        } else {
+            None
+        };
+        let uniformity = self.uniformity.map(|test| test.of(histogram));
+        let histogram = if self.histogram || self.divergence {
+            Some(histogram.clone())
+        } else {
+            None
+        };
+        Ok(Chunk {
+            value,
+            ent,
+            markov: match self.markov {
+                Some(order) => Some(MarkovReport::of(input, order)?),
+                None => None,
+            },
+            uniformity,
+            lz: if self.lz {
+                Some(slice_lz_complexity(input))
+            } else {
+                None
+            },
+            histogram,
+            divergence: None,
+            class,
+            bytes: if self.bytes {
+                Some(input.to_vec())
+            } else {
+                None
+            },
+            signatures: None,
+            section: None,
+            segment: None,
+        })
+    }
+
+    /// The (description, csv header) of the values calculated
+    fn columns(&self) -> Vec<(String, String)> {
+        let mut columns = vec![
+            ("startbyte".to_string(), "start".to_string()),
+            ("entropy/byte".to_string(), "entropy".to_string()),
+        ];
+        if self.ent {
+            columns.push(("chi-square".to_string(), "chisq".to_string()));
+            columns.push(("p-value".to_string(), "p".to_string()));
+            columns.push(("mean".to_string(), "mean".to_string()));
+            columns.push(("monte carlo pi".to_string(), "pi".to_string()));
+            columns.push(("serial correlation".to_string(), "scc".to_string()));
+        }
+        if let Some(order) = self.markov {
+            columns.push((
+                format!("order-{} conditional entropy/byte", order),
+                format!("h{}", order),
+            ));
+            columns.push(("structured (0/1)".to_string(), "structured".to_string()));
+        }
+        if self.uniformity.is_some() {
+            columns.push(("entropy lower bound".to_string(), "lower".to_string()));
+            columns.push(("entropy upper bound".to_string(), "upper".to_string()));
+            columns.push(("g-test p-value".to_string(), "p_uniform".to_string()));
+            columns.push(("random (0/1)".to_string(), "random".to_string()));
+        }
+        if self.lz {
+            columns.push(("lz77 bits/byte".to_string(), "lz".to_string()));
+        }
+        if self.divergence {
+            columns.push(("js divergence".to_string(), "jsd".to_string()));
+        }
+        if self.classify {
+            columns.push(("class".to_string(), "class".to_string()));
+        }
+        if self.bytes {
+            columns.push((
+                "signatures (name@offset ...)".to_string(),
+                "signatures".to_string(),
+            ));
+        }
+        if self.elf {
+            columns.push(("elf section".to_string(), "section".to_string()));
+            columns.push(("elf segment".to_string(), "segment".to_string()));
+        }
+        columns
+    }
+}
+
+/// Receives the chunks in the order of their offsets.
+trait Output {
+    fn start(&mut self);
+    fn record(&mut self, offset: u64, chunk: &Chunk);
+    /// Called after the last chunk, with the statistics of the whole input for --ent if the scan
+    /// succeeded and the signatures found where no chunk was recorded, e.g. in an input shorter
+    /// than a chunk
+    fn finish(&mut self, total: Option<&EntReport>, hits: &[Hit]);
+}
+
+/// Raw data as `<startbyte>;<entropy>`, optionally followed by further statistics
+struct Csv {
+    measure: Measure,
+    window: Option<u32>,
+}
+
+impl Output for Csv {
+    fn start(&mut self) {
+        let columns = self.measure.columns();
+        let format: Vec<String> = columns.iter().map(|(d, _)| format!("<{}>", d)).collect();
+        let mut header: Vec<String> = columns.iter().map(|(_, h)| format!("\"{}\"", h)).collect();
+        // the offsets only tell the step, so the window length goes into the header
+        if let Some(window) = self.window {
+            header[1] = format!("\"entropy over {} bytes\"", window);
+        }
+        eprintln!(
+            "Outputting raw data as csv in the format {}",
+            format.join(";")
+        );
+        println!("{}", header.join(";"));
+    }
+
+    fn record(&mut self, offset: u64, chunk: &Chunk) {
+        print!("{};{:.6}", offset, chunk.value);
+        if let Some(ent) = &chunk.ent {
+            let r = ent.report();
+            print!(";{:.6};{:.6};{:.6}", r.chi_square, r.p_value, r.mean);
+            // undefined values are left empty
+            match r.monte_carlo_pi {
+                Some(pi) => print!(";{:.6}", pi),
+                None => print!(";"),
+            }
+            match r.serial_correlation {
+                Some(scc) => print!(";{:.6}", scc),
+                None => print!(";"),
+            }
+        }
+        if let Some(m) = &chunk.markov {
+            print!(";{:.6};{}", m.conditional, m.is_structured() as u8);
+        }
+        if let Some(u) = &chunk.uniformity {
+            print!(
+                ";{:.6};{:.6};{:.6};{}",
+                u.lower, u.upper, u.p_value, u.random as u8
+            );
+        }
+        if let Some(lz) = chunk.lz {
+            print!(";{:.6}", lz);
+        }
+        if self.measure.divergence {
+            match chunk.divergence {
+                Some(d) => print!(";{:.6}", d),
+                None => print!(";"),
+            }
+        }
+        if let Some(class) = chunk.class {
+            print!(";{}", class);
+        }
+        if let Some(hits) = &chunk.signatures {
+            let hits: Vec<String> = hits.iter().map(|h| h.to_string()).collect();
+            print!(";{}", hits.join(" "));
+        }
+        if self.measure.elf {
+            print!(
+                ";{};{}",
+                chunk.section.as_deref().unwrap_or(""),
+                chunk.segment.as_deref().unwrap_or("")
+            );
+        }
+        println!();
+    }
+
+    fn finish(&mut self, total: Option<&EntReport>, hits: &[Hit]) {
+        // keep the csv on stdout parseable
+        unassigned(hits);
+        if let Some(total) = total {
+            eprintln!("{}", total);
+        }
+    }
+}
+
+/// A [ScanReport] printed as one json document once the scan is done
+struct Json {
+    report: ScanReport,
+}
+
+impl Output for Json {
+    fn start(&mut self) {}
+
+    fn record(&mut self, offset: u64, chunk: &Chunk) {
+        let len = self.report.chunk_size as usize;
+        self.report.push(chunk.record(offset, len));
+    }
+
+    fn finish(&mut self, total: Option<&EntReport>, hits: &[Hit]) {
+        self.report.summary.ent = total.cloned();
+        self.report.summary.signatures = hits.to_vec();
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&self.report).expect("reports serialise")
+        );
+    }
+}
+
+/// One json [ChunkRecord] per line, written as the chunks come in
+struct Ndjson {
+    // the number of bytes in a chunk
+    len: usize,
+}
+
+impl Output for Ndjson {
+    fn start(&mut self) {}
+
+    fn record(&mut self, offset: u64, chunk: &Chunk) {
+        println!(
+            "{}",
+            serde_json::to_string(&chunk.record(offset, self.len)).expect("records serialise")
+        );
+    }
+
+    fn finish(&mut self, total: Option<&EntReport>, hits: &[Hit]) {
+        unassigned(hits);
+        if let Some(total) = total {
+            eprintln!("{}", total);
+        }
+    }
+}
+
+/// Colour-coded blocks, [COLUMNS] per row, framed by a ruler
+struct Map {
+    // the number of bytes between the offsets of two neighbouring blocks
+    stride: u64,
+    // printed next to the ruler
+    label: String,
+    blocks: usize,
+    colouring: Colouring,
+    // marks the chunks indistinguishable from random
+    uniformity: Option<UniformityTest>,
+    // the signatures found in the current row, listed below it
+    hits: Vec<Hit>,
+    // (offset, name) of the ELF sections by offset, marked below the row they start in
+    boundaries: Vec<(u64, String)>,
+    // listed after the map
+    elf: Option<ElfReport>,
+}
+
+impl Map {
+    /// Finish the current row and list the signatures found in it
+    fn end_row(&mut self) {
+        address(self.blocks as u64 * self.stride);
+        let first = (self.blocks - 1) / COLUMNS * COLUMNS;
+        let row = first as u64 * self.stride..self.blocks as u64 * self.stride;
+        let mut marks: Vec<(usize, String)> = Vec::new();
+        for (offset, name) in self.boundaries.iter().filter(|(o, _)| row.contains(o)) {
+            let column = ((offset - row.start) / self.stride) as usize;
+            match marks.last_mut() {
+                Some((c, names)) if *c == column => {
+                    names.push(',');
+                    names.push_str(name);
+                }
+                _ => marks.push((column, name.clone())),
+            }
+        }
+        if !marks.is_empty() {
+            boundaries(&marks);
+        }
+        if !self.hits.is_empty() {
+            let shown: Vec<String> = self
+                .hits
+                .iter()
+                .take(MAX_HITS)
+                .map(|h| format!("{:#x} {}", h.offset, h.name))
+                .collect();
+            print!("\n  \x1b[38;5;13m{}\x1b[0m", shown.join("  "));
+            if self.hits.len() > MAX_HITS {
+                print!(" and {} more", self.hits.len() - MAX_HITS);
+            }
+            self.hits.clear();
+        }
+    }
+}
+
+/// Signatures listed below a row of the map at most
+const MAX_HITS: usize = 8;
+
+/// Holds the chunks back until the signature scan has passed their end
+struct Signatures {
+    scanner: SignatureScanner,
+    // (offset, length, chunk)
+    pending: VecDeque<(u64, usize, Chunk)>,
+    // found, but not yet assigned to a chunk
+    hits: VecDeque<Hit>,
+}
+
+impl Signatures {
+    fn push(&mut self, offset: u64, mut chunk: Chunk, out: &mut dyn Output) {
+        let bytes = chunk.bytes.take().unwrap_or_default();
+        self.hits.extend(self.scanner.update(&bytes));
+        self.pending.push_back((offset, bytes.len(), chunk));
+        self.release(out, false);
+    }
+
+    /// Record the chunks the scan has passed, or all of them after the end of the input. Then the
+    /// last chunk takes the hits after its end as well, from the trailing partial chunk.
+    fn release(&mut self, out: &mut dyn Output, all: bool) {
+        while let Some((offset, len, _)) = self.pending.front() {
+            let end = offset + *len as u64;
+            if !all && end > self.scanner.settled() {
+                break;
+            }
+            let last = all && self.pending.len() == 1;
+            let (offset, _, mut chunk) = self.pending.pop_front().unwrap();
+            let mut hits = Vec::new();
+            while self.hits.front().is_some_and(|h| last || h.offset < end) {
+                hits.extend(self.hits.pop_front());
+            }
+            chunk.signatures = Some(hits);
+            out.record(offset, &chunk);
+        }
+    }
+
+    /// Search the trailing partial chunk a scan leaves out and record the rest of the chunks.
+    /// Returns the hits no chunk was recorded for.
+    fn finish(&mut self, tail: &[u8], out: &mut dyn Output) -> Vec<Hit> {
+        self.hits.extend(self.scanner.update(tail));
+        self.hits.extend(self.scanner.finish());
+        self.release(out, true);
+        self.hits.drain(..).collect()
+    }
+}
+
+/// What the blocks of the map are coloured by
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Colouring {
+    Entropy,
+    Lz,
+    Divergence,
+    Class,
+}
+
+impl Output for Map {
+    fn start(&mut self) {
+        if self.colouring == Colouring::Class {
+            println!("Class color map:");
+            for class in Class::ALL.iter() {
+                println!(
+                    "\x1b[38;5;{}m█\x1b[0m {:<10} {}",
+                    class_colour(*class),
+                    class,
+                    class.description()
+                );
+            }
+            println!();
+        } else {
+            println!("Entropy color map:");
+            print!("Low ");
+            for (_, rgb) in PALETTE.iter() {
+                print!("\x1b[48;5;{}m  \x1b[0m", cube(*rgb));
+            }
+            println!("\x1b[0m High\n\x1b[0m");
+        }
+        if let Some(test) = self.uniformity {
+            println!(
+                "▓ indistinguishable from random bytes (p ≥ {})\n",
+                test.alpha()
+            );
+        }
+        ruler(&self.label);
+    }
+
+    fn record(&mut self, _offset: u64, chunk: &Chunk) {
+        if self.blocks.is_multiple_of(COLUMNS) {
+            if self.blocks > 0 {
+                self.end_row();
+            }
+            println!();
+        }
+        if let Some(hits) = &chunk.signatures {
+            self.hits.extend(hits.iter().cloned());
+        }
+        // structured data which only looks random by its histogram is shaded
+        let block = match (&chunk.markov, &chunk.uniformity) {
+            (Some(m), _) if m.is_structured() => "▒",
+            (_, Some(u)) if u.random => "▓",
+            _ => "█",
+        };
+        // the lz estimate is on the same scale of 0 to 8 bits per byte, the divergence is at most
+        // 1 bit and missing for the first chunk
+        let colour = match self.colouring {
+            Colouring::Entropy => entropy_colour(chunk.value),
+            Colouring::Lz => entropy_colour(chunk.lz.unwrap_or(chunk.value)),
+            Colouring::Divergence => entropy_colour(chunk.divergence.unwrap_or(0.0) * 8.0),
+            Colouring::Class => class_colour(chunk.class.unwrap_or(Class::Other)),
+        };
+        print!("\x1b[38;5;{}m{}\x1b[0m", colour, block);
+        self.blocks += 1;
+    }
+
+    fn finish(&mut self, total: Option<&EntReport>, hits: &[Hit]) {
+        if self.blocks > 0 {
+            self.end_row();
+        }
+        println!();
+        ruler(&self.label);
+        println!();
+        if !hits.is_empty() {
+            let shown: Vec<String> = hits
+                .iter()
+                .map(|h| format!("{:#x} {}", h.offset, h.name))
+                .collect();
+            println!();
+            println!("No chunk to list the signatures under:");
+            println!("  \x1b[38;5;13m{}\x1b[0m", shown.join("  "));
+        }
+        if let Some(total) = total {
+            println!();
+            println!("{}", total);
+        }
+        if let Some(elf) = &self.elf {
+            elf_table(elf);
+        }
+    }
 }
 
 fn main() {
     let cfg = Tropy::from_args();
-    let mut r: Box<dyn BufRead> = {
-        if cfg.file.eq("-") {
-            let s = io::stdin();
-            eprintln!("* Using stdin for input");
-            let r = BufReader::with_capacity(2048usize, s);
-            Box::new(r)
-        } else {
-            let s = match File::open(&cfg.file) {
-                Ok(s) => s,
-                Err(e) => {
-                    eprintln!("Opening file failed with: {}", e.description());
-                    exit(e.raw_os_error().unwrap_or(1))
+    if let Err(e) = run(cfg) {
+        eprintln!("{}", e);
+        exit(exit_code(&e))
+    }
+}
+
+/// The exit code documented in [EXIT_CODES]
+fn exit_code(e: &Error) -> i32 {
+    match e {
+        Error::ZeroLength(_) | Error::InvalidArgument(_) => 1,
+        Error::UnsupportedFormat(_) => 2,
+        Error::Io(_) => 3,
+        Error::Overflow => 4,
+        // variants added later are treated as failures of the input
+        _ => 3,
+    }
+}
+
+fn run(cfg: Tropy) -> Result<(), Error> {
+    if let Some(Command::Regions {
+        file,
+        bytes,
+        metric,
+        estimator,
+        penalty,
+        threads,
+        csv,
+    }) = cfg.command
+    {
+        let penalty = penalty.unwrap_or(DEFAULT_PENALTY);
+        return regions(&file, bytes, metric, estimator, penalty, threads, csv);
+    }
+    if let Some(Command::Diff {
+        a,
+        b,
+        bytes,
+        threshold,
+        threads,
+        csv,
+    }) = cfg.command
+    {
+        return diff(&a, &b, bytes, threshold, threads, csv);
+    }
+    if let Some(Command::Render { file, save_profile }) = cfg.command {
+        return render(&file, save_profile.as_deref());
+    }
+    let file = match cfg.file.clone() {
+        Some(file) => file,
+        None => structopt::clap::Error::with_description(
+            "The following required arguments were not provided: <input>",
+            structopt::clap::ErrorKind::MissingRequiredArgument,
+        )
+        .exit(),
+    };
+    let format = match (cfg.csv, cfg.format) {
+        (false, format) => format.unwrap_or(Format::Map),
+        (true, None) | (true, Some(Format::Csv)) => Format::Csv,
+        (true, Some(_)) => {
+            return Err(Error::InvalidArgument(
+                "--csv can't be combined with another --format".to_string(),
+            ))
+        }
+    };
+    let mut r = open(&file)?;
+
+    let (stride, label) = match cfg.window {
+        Some(window) => {
+            if window == 0 {
+                return Err(Error::ZeroLength("window size (--window)"));
+            }
+            if cfg.step == 0 {
+                return Err(Error::ZeroLength("step (--step)"));
+            }
+            eprintln!(
+                "*\x1b[38;5;10mUsing a sliding window of {}bytes advancing {}bytes per step\x1b[0m",
+                window, cfg.step
+            );
+            (
+                cfg.step as usize,
+                format!("window={}B step={}B", window, cfg.step),
+            )
+        }
+        None => {
+            if cfg.bytes == 0 {
+                return Err(Error::ZeroLength("chunk size (--bytes)"));
+            }
+            eprintln!("*\x1b[38;5;10mUsing chunks of {}bytes\x1b[0m", cfg.bytes);
+            (cfg.bytes as usize, format!("blksize={}B", cfg.bytes))
+        }
+    };
+
+    if (cfg.ent
+        || cfg.markov.is_some()
+        || cfg.significance.is_some()
+        || cfg.lz
+        || cfg.divergence.is_some()
+        || cfg.classify
+        || cfg.signatures
+        || cfg.rules.is_some()
+        || cfg.elf
+        || cfg.symbol != Symbol::Byte)
+        && cfg.window.is_some()
+    {
+        return Err(Error::InvalidArgument(
+            "--ent, --markov, --significance, --lz, --divergence, --classify, --signatures, --rules, --elf and --symbol can't be combined with --window"
+                .to_string(),
+        ));
+    }
+    if [cfg.lz, cfg.divergence.is_some(), cfg.classify]
+        .iter()
+        .filter(|c| **c)
+        .count()
+        > 1
+        && format == Format::Map
+    {
+        return Err(Error::InvalidArgument(
+            "The map can be coloured by only one of --lz, --divergence and --classify".to_string(),
+        ));
+    }
+    if cfg.histogram {
+        let mut h = Histogram::new();
+        let mut buf = [0u8; 1 << 16];
+        loop {
+            match r.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => h.update(&buf[..n])?,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(reading(e)),
+            }
+        }
+        print!("{}", h);
+        return Ok(());
+    }
+    let mut divergence = match &cfg.divergence {
+        None => None,
+        Some(Reference::Previous) => Some(Divergence::Previous(None)),
+        Some(Reference::File(path)) => {
+            let reference = std::fs::read_to_string(path)
+                .map_err(|e| {
+                    Error::Io(io::Error::new(
+                        e.kind(),
+                        format!(
+                            "Reading the reference histogram {} failed with: {}",
+                            path, e
+                        ),
+                    ))
+                })?
+                .parse::<Histogram>()
+                .map_err(|e| match e {
+                    Error::UnsupportedFormat(msg) => Error::UnsupportedFormat(format!(
+                        "The reference histogram {} is invalid: {}",
+                        path, msg
+                    )),
+                    e => e,
+                })?;
+            Some(Divergence::Fixed(reference))
+        }
+    };
+    let rules = match &cfg.rules {
+        None => None,
+        Some(path) => Some(
+            std::fs::read_to_string(path)
+                .map_err(|e| {
+                    Error::Io(io::Error::new(
+                        e.kind(),
+                        format!("Reading the rule file {} failed with: {}", path, e),
+                    ))
+                })?
+                .parse::<RuleSet>()
+                .map_err(|e| match e {
+                    Error::UnsupportedFormat(msg) => Error::UnsupportedFormat(format!(
+                        "The rule file {} is invalid: {}",
+                        path, msg
+                    )),
+                    e => e,
+                })?,
+        ),
+    };
+    let elf = if cfg.elf {
+        if file == "-" {
+            return Err(Error::InvalidArgument(
+                "--elf needs a file, stdin can't be read twice".to_string(),
+            ));
+        }
+        let context = |e: Error| match e {
+            Error::UnsupportedFormat(msg) => {
+                Error::UnsupportedFormat(format!("{} can't be read as ELF: {}", file, msg))
+            }
+            Error::Io(e) => Error::Io(io::Error::new(
+                e.kind(),
+                format!("Reading {} failed with: {}", file, e),
+            )),
+            e => e,
+        };
+        // only the headers and one buffer at a time are kept, not the whole file
+        let mut input = File::open(&file).map_err(|e| context(e.into()))?;
+        let elf = Elf::read_from(&mut input).map_err(context)?;
+        eprintln!(
+            "*\x1b[38;5;10mReading an ELF{} {} {} binary with {} sections and {} segments\x1b[0m",
+            if elf.is_64 { 64 } else { 32 },
+            if elf.little_endian { "LSB" } else { "MSB" },
+            elf.machine_name(),
+            elf.sections.len(),
+            elf.segments.len()
+        );
+        let report = elf.report_from(&mut input).map_err(context)?;
+        Some((elf, report))
+    } else {
+        None
+    };
+    if cfg.estimator != Estimator::PlugIn
+        && (cfg.metric != Metric::Shannon || cfg.symbol != Symbol::Byte)
+    {
+        return Err(Error::InvalidArgument(
+            "--estimator only applies to the Shannon entropy of bytes, not to --metric or --symbol"
+                .to_string(),
+        ));
+    }
+    if let Some(order) = cfg.markov {
+        if order == 0 || order > MarkovCalculator::MAX_ORDER {
+            return Err(Error::InvalidArgument(format!(
+                "--markov must be between 1 and {}",
+                MarkovCalculator::MAX_ORDER
+            )));
+        }
+    }
+    let uniformity = match cfg.significance {
+        Some(alpha) => Some(UniformityTest::new(alpha)?),
+        None => None,
+    };
+    let measure = Measure {
+        metric: cfg.metric,
+        estimator: cfg.estimator,
+        symbol: cfg.symbol,
+        ent: cfg.ent,
+        markov: cfg.markov,
+        uniformity,
+        lz: cfg.lz,
+        divergence: divergence.is_some(),
+        classify: cfg.classify,
+        histogram: false,
+        bytes: cfg.signatures || rules.is_some(),
+        elf: elf.is_some(),
+    };
+
+    // the chunk size is the window in a sliding window, the stride the step
+    let len = cfg.window.unwrap_or(cfg.bytes) as usize;
+    let mut out: Box<dyn Output> = match format {
+        Format::Csv => Box::new(Csv {
+            measure,
+            window: cfg.window,
+        }),
+        Format::Json => Box::new(Json {
+            report: ScanReport {
+                elf: elf.as_ref().map(|(_, report)| report.clone()),
+                ..ScanReport::new(Input::of(&file), len as u64, stride as u64)
+            },
+        }),
+        Format::Ndjson => Box::new(Ndjson { len }),
+        Format::Map => Box::new(Map {
+            stride: stride as u64,
+            label,
+            blocks: 0,
+            colouring: if cfg.lz {
+                Colouring::Lz
+            } else if divergence.is_some() {
+                Colouring::Divergence
+            } else if cfg.classify {
+                Colouring::Class
+            } else {
+                Colouring::Entropy
+            },
+            uniformity,
+            hits: Vec::new(),
+            boundaries: match &elf {
+                Some((elf, _)) => {
+                    let mut starts: Vec<(u64, String)> = elf
+                        .sections
+                        .iter()
+                        .filter(|s| s.in_file() && !s.name.is_empty())
+                        .map(|s| (s.offset, s.name.clone()))
+                        .collect();
+                    starts.sort();
+                    starts
                 }
+                None => Vec::new(),
+            },
+            elf: elf.as_ref().map(|(_, report)| report.clone()),
+        }),
+    };
+
+    let threads = workers(cfg.threads);
+    let seekable = seekable(&file);
+
+    if cfg.metric != Metric::Shannon {
+        eprintln!("*\x1b[38;5;10mUsing the {} entropy\x1b[0m", cfg.metric);
+    }
+    if cfg.estimator != Estimator::PlugIn {
+        eprintln!(
+            "*\x1b[38;5;10mUsing the {} estimate of the entropy\x1b[0m",
+            cfg.estimator
+        );
+    }
+    if cfg.symbol != Symbol::Byte {
+        eprintln!(
+            "*\x1b[38;5;10mCounting {} symbols, scaled to bits per byte\x1b[0m",
+            cfg.symbol
+        );
+        // a chunk can't hold more distinct symbols than it has symbols
+        let bits = cfg.symbol.bits() as f64;
+        let symbols = cfg.bytes as f64 * 8.0 / bits;
+        let ceiling = symbols.log2().min(bits) * 8.0 / bits;
+        if ceiling < 8.0 {
+            eprintln!(
+                "*\x1b[38;5;11mA chunk holds {} symbols, so even random data reaches at most {:.2} bits per byte\x1b[0m",
+                symbols, ceiling
+            );
+        }
+    }
+
+    if cfg.lz && format == Format::Map {
+        eprintln!("*\x1b[38;5;10mColouring by the LZ77 compressed size\x1b[0m");
+    }
+    if let (Some(reference), Format::Map) = (&cfg.divergence, format) {
+        let reference = match reference {
+            Reference::Previous => "the previous chunk".to_string(),
+            Reference::File(path) => path.clone(),
+        };
+        eprintln!(
+            "*\x1b[38;5;10mColouring by the divergence from {}\x1b[0m",
+            reference
+        );
+    }
+
+    out.start();
+
+    // the statistics of the whole input are merged from the ones of the chunks
+    let mut total = EntCalculator::new();
+    let mut values = Vec::new();
+    let mut signatures = if cfg.signatures || rules.is_some() {
+        let scanner = match rules {
+            Some(rules) => {
+                eprintln!(
+                    "*\x1b[38;5;10mLooking for signatures and {} rules\x1b[0m",
+                    rules.len()
+                );
+                SignatureScanner::with_rules(rules)
+            }
+            None => {
+                eprintln!("*\x1b[38;5;10mLooking for signatures\x1b[0m");
+                SignatureScanner::new()
+            }
+        };
+        Some(Signatures {
+            scanner,
+            pending: VecDeque::new(),
+            hits: VecDeque::new(),
+        })
+    } else {
+        None
+    };
+    let mut sink = |offset: u64, mut chunk: Chunk| {
+        if cfg.save_profile.is_some() {
+            values.push(chunk.value);
+        }
+        if let Some(ent) = &chunk.ent {
+            total.merge(ent)?;
+        }
+        if let (Some(d), Some(h)) = (divergence.as_mut(), chunk.histogram.take()) {
+            chunk.divergence = d.next(h);
+        }
+        if let Some((elf, _)) = &elf {
+            chunk.section = elf.section_at(offset).map(|s| s.name.clone());
+            chunk.segment = elf.segment_at(offset).map(|s| s.to_string());
+        }
+        match signatures.as_mut() {
+            Some(s) => s.push(offset, chunk, &mut *out),
+            None => out.record(offset, &chunk),
+        }
+        Ok(())
+    };
+    // the profile records the hash of the bytes scanned, so the input isn't read twice
+    let mut hasher = cfg.save_profile.as_ref().map(|_| Sha256::new());
+    let scanned = match cfg.window {
+        None if seekable && threads > 1 => {
+            parallel(&file, stride, threads, measure, hasher.as_mut(), &mut sink)
+        }
+        window => {
+            let mut input = Hashing {
+                inner: &mut *r,
+                hasher: hasher.as_mut(),
             };
-            eprintln!("*\x1b[38;5;10mUsing {} for input\x1b[0m", cfg.file);
-            let r = BufReader::with_capacity(2048usize, s);
-            Box::new(r)
+            match window {
+                Some(window) => rolling(&mut input, window as usize, stride, measure, &mut sink)
+                    .map(|()| Vec::new()),
+                None => chunked(&mut input, stride, measure, &mut sink),
+            }
+            .and_then(|tail| input.finish().map(|()| tail))
+        }
+    };
+    // the chunks leave out the trailing partial chunk, which --ent summarises and the signature
+    // scan searches as well
+    let scanned = scanned.and_then(|tail| {
+        if cfg.ent {
+            total.update(&tail)?;
         }
+        Ok(tail)
+    });
+    let unassigned = match signatures.as_mut() {
+        Some(s) => s.finish(scanned.as_deref().unwrap_or_default(), &mut *out),
+        None => Vec::new(),
+    };
+    let total = match (&scanned, cfg.ent) {
+        (Ok(_), true) => Some(total.report()),
+        _ => None,
     };
+    // close the map of the chunks read so far
+    out.finish(total.as_ref(), &unassigned);
+    scanned?;
 
-    let mut chunksize = cfg.bytes as usize;
-    let mut buf = vec![0u8; chunksize];
-    let mut c = Calculator::new();
-    let mut chunknum = 0usize;
-    let mut addr = 0;
-    eprintln!("*\x1b[38;5;10mUsing chunks of {}bytes\x1b[0m", chunksize);
-
-    if !cfg.csv {
-  let test = String::from("  ");
-  println!("Entropy color map:");     
-  let colored_test = ansi_rgb_string(test.clone(),0,0,0);
-          print!("Low {}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),0,0,1);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),0,0,2);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),0,0,3);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),0,0,4);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),0,0,5);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),0,1,5);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),0,1,4);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),0,1,3);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),0,2,4);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),1,4,5);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),2,5,5);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),2,4,5);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),1,2,4);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),1,2,5);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),2,2,5);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),2,1,5);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),2,0,5);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),2,0,4);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),1,0,4);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),1,0,3);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),1,0,2);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),2,0,3);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),3,0,4);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),4,0,5);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),4,0,4);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),5,0,5);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),5,0,4);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),5,0,3);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),4,0,3);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),4,0,2);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),4,0,1);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),5,0,2);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),5,0,1);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),5,0,0);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),4,0,0);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),3,0,0);
-          print!("{}", colored_test);
-  let colored_test =  ansi_rgb_string(test.clone(),2,0,0);
-          print!("\x1b[0m{} High\n\x1b[0m", colored_test);
+    if let Some(path) = &cfg.save_profile {
+        let input = Input::of(&file);
+        let profile = Profile {
+            source: file.clone(),
+            size: input.size,
+            hash: hasher.map(|h| h.finalize().into()),
+            chunk_size: len as u64,
+            stride: stride as u64,
+            metric: measure.name(),
+            values,
+        };
+        save(&profile, path)?;
+        eprintln!("*\x1b[38;5;10mSaved the profile to {}\x1b[0m", path);
+    }
+    Ok(())
+}
+
+/// The Shannon entropy of every chunk of a file
+fn entropies(file: &str, bytes: usize, threads: usize) -> Result<Vec<f64>, Error> {
+    let mut r = open(file)?;
+    let measure = Measure {
+        metric: Metric::Shannon,
+        estimator: Estimator::PlugIn,
+        symbol: Symbol::Byte,
+        ent: false,
+        markov: None,
+        uniformity: None,
+        lz: false,
+        divergence: false,
+        classify: false,
+        histogram: false,
+        bytes: false,
+        elf: false,
+    };
+    let mut values = Vec::new();
+    let mut sink = |_offset: u64, chunk: Chunk| {
+        values.push(chunk.value);
+        Ok(())
+    };
+    if seekable(file) && threads > 1 {
+        parallel(file, bytes, threads, measure, None, &mut sink)?;
     } else {
-        // use raw data
-        eprintln!("Outputting raw data as csv in the format <startbyte>;<entropy/byte>");
-        println!("\"start\";\"entropy\"");
+        chunked(&mut r, bytes, measure, &mut sink)?;
     }
+    Ok(values)
+}
+
+/// Compare the entropy profiles of two files
+fn diff(
+    a: &str,
+    b: &str,
+    bytes: u32,
+    threshold: f64,
+    threads: Option<usize>,
+    csv: bool,
+) -> Result<(), Error> {
+    if bytes == 0 {
+        return Err(Error::ZeroLength("chunk size (--bytes)"));
+    }
+    if threshold.is_nan() || threshold < 0.0 {
+        return Err(Error::InvalidArgument(
+            "--threshold must not be negative".to_string(),
+        ));
+    }
+    if a == "-" && b == "-" {
+        return Err(Error::InvalidArgument(
+            "Only one of the inputs can be stdin".to_string(),
+        ));
+    }
+    let threads = workers(threads);
+    let first = entropies(a, bytes as usize, threads)?;
+    let second = entropies(b, bytes as usize, threads)?;
+    let found = differences(&first, &second, bytes as u64, threshold)?;
+    let chunks = first.len().max(second.len());
+
+    if csv {
+        // chunks only one of the inputs has leave the other columns empty
+        println!("\"start\";\"a\";\"b\";\"delta\"");
+        for i in 0..chunks {
+            let (x, y) = (first.get(i), second.get(i));
+            let column = |v: Option<&f64>| v.map(|v| format!("{:.6}", v)).unwrap_or_default();
+            let delta = match (x, y) {
+                (Some(x), Some(y)) => Some(y - x),
+                _ => None,
+            };
+            println!(
+                "{};{};{};{}",
+                i as u64 * bytes as u64,
+                column(x),
+                column(y),
+                column(delta.as_ref())
+            );
+        }
+        return Ok(());
+    }
+
+    println!("Entropy color map:");
+    print!("Low ");
+    for (_, rgb) in PALETTE.iter() {
+        print!("\x1b[48;5;{}m  \x1b[0m", cube(*rgb));
+    }
+    println!("\x1b[0m High\n\x1b[0m");
+    println!("Rows: a, b and the difference |b - a| on the same scale, ▲/▼ where b is more than {} bits higher/lower\n", threshold);
+    ruler(&format!("blksize={}B", bytes));
     println!();
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m5\x1b[0m");
-    println!();
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m5\x1b[0m");
-    print!("\x1b[38;5;11m6\x1b[0m");
-    print!("\x1b[38;5;11m7\x1b[0m");
-    print!("\x1b[38;5;11m8\x1b[0m");
-    print!("\x1b[38;5;11m9\x1b[0m");
-    print!("\x1b[38;5;11ma\x1b[0m");
-    print!("\x1b[38;5;11mb\x1b[0m");
-    print!("\x1b[38;5;11mc\x1b[0m");
-    print!("\x1b[38;5;11md\x1b[0m");
-    print!("\x1b[38;5;11me\x1b[0m");
-    print!("\x1b[38;5;11mf\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m5\x1b[0m");
-    print!("\x1b[38;5;11m6\x1b[0m");
-    print!("\x1b[38;5;11m7\x1b[0m");
-    print!("\x1b[38;5;11m8\x1b[0m");
-    print!("\x1b[38;5;11m9\x1b[0m");
-    print!("\x1b[38;5;11ma\x1b[0m");
-    print!("\x1b[38;5;11mb\x1b[0m");
-    print!("\x1b[38;5;11mc\x1b[0m");
-    print!("\x1b[38;5;11md\x1b[0m");
-    print!("\x1b[38;5;11me\x1b[0m");
-    print!("\x1b[38;5;11mf\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m5\x1b[0m");
-    print!("\x1b[38;5;11m6\x1b[0m");
-    print!("\x1b[38;5;11m7\x1b[0m");
-    print!("\x1b[38;5;11m8\x1b[0m");
-    print!("\x1b[38;5;11m9\x1b[0m");
-    print!("\x1b[38;5;11ma\x1b[0m");
-    print!("\x1b[38;5;11mb\x1b[0m");
-    print!("\x1b[38;5;11mc\x1b[0m");
-    print!("\x1b[38;5;11md\x1b[0m");
-    print!("\x1b[38;5;11me\x1b[0m");
-    print!("\x1b[38;5;11mf\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m5\x1b[0m");
-    print!("\x1b[38;5;11m6\x1b[0m");
-    print!("\x1b[38;5;11m7\x1b[0m");
-    print!("\x1b[38;5;11m8\x1b[0m");
-    print!("\x1b[38;5;11m9\x1b[0m");
-    print!("\x1b[38;5;11ma\x1b[0m");
-    print!("\x1b[38;5;11mb\x1b[0m");
-    print!("\x1b[38;5;11mc\x1b[0m");
-    print!("\x1b[38;5;11md\x1b[0m");
-    print!("\x1b[38;5;11me\x1b[0m");
-    print!("\x1b[38;5;11mf\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m5\x1b[0m");
-    print!("\x1b[38;5;11m6\x1b[0m");
-    print!("\x1b[38;5;11m7\x1b[0m");
-    print!("\x1b[38;5;11m8\x1b[0m");
-    print!("\x1b[38;5;11m9\x1b[0m");
-    print!("\x1b[38;5;11ma\x1b[0m");
-    print!("\x1b[38;5;11mb\x1b[0m");
-    print!("\x1b[38;5;11mc\x1b[0m");
-    print!("\x1b[38;5;11md\x1b[0m");
-    print!("\x1b[38;5;11me\x1b[0m");
-    print!("\x1b[38;5;11mf\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m blksize={}B\x1b[0m",chunksize);
-    while let Ok(_) = r.read_exact(&mut buf[..]).and_then(|_| c.write(&buf[..])) {
-        let e = c.entropy();
-
-        if !cfg.csv {
-            if chunknum % 80 == 0 {
-              if addr == 0 { 
-                addr = addr + (80 * chunksize);
-              }
-              else {
-                print!(" \x1b[38;5;208m{:#04x}\x1b[0m", addr);
-                addr = addr + (80 * chunksize);
-              }
-              println!();
+    for row in (0..chunks).step_by(COLUMNS) {
+        let cells = row..(row + COLUMNS).min(chunks);
+        for (values, name) in [(&first, "a"), (&second, "b")].iter() {
+            for i in cells.clone() {
+                match values.get(i) {
+                    Some(v) => print!("\x1b[38;5;{}m█\x1b[0m", entropy_colour(*v)),
+                    None => print!(" "),
+                }
+            }
+            print!("{:>1$}", "", COLUMNS - cells.len());
+            address(cells.end.min(values.len()) as u64 * bytes as u64);
+            println!(" {}", name);
+        }
+        for i in cells.clone() {
+            match (first.get(i), second.get(i)) {
+                (Some(x), Some(y)) => {
+                    let delta = y - x;
+                    let block = if delta > threshold {
+                        "▲"
+                    } else if delta < -threshold {
+                        "▼"
+                    } else {
+                        "█"
+                    };
+                    print!("\x1b[38;5;{}m{}\x1b[0m", entropy_colour(delta.abs()), block);
+                }
+                _ => print!(" "),
             }
-            // scale entropy to bits (i.e. value/8)
-            // i.e. perfectly uniform data would have an entropy of 1 (i.e. 8bits/byte)
-            let h = (240.0 + e / 8.0 * 120.0) as u32;
-            //print!("{} ", h);
-            let out = ansi_rgb_string_special("█".to_string(), h, 1, 1);
-            print!("{}", out);
+        }
+        println!();
+        println!();
+    }
+    ruler(&format!("blksize={}B", bytes));
+    println!();
+    println!();
+
+    if first.len() != second.len() {
+        let (longer, more) = if first.len() > second.len() {
+            ("a", first.len() - second.len())
         } else {
-            // output as csv
-            println!("{};{:.6}", chunknum * chunksize, e);
+            ("b", second.len() - first.len())
+        };
+        println!(
+            "{} has {} more chunks ({}bytes) which aren't compared",
+            longer,
+            more,
+            more as u64 * bytes as u64
+        );
+    }
+    if found.is_empty() {
+        println!("No chunks differ by more than {} bits", threshold);
+        return Ok(());
+    }
+    println!(
+        "\x1b[38;5;11m  {:>12} {:>12} {:>12} {:>8} {:>8}\x1b[0m",
+        "start", "end", "length", "mean", "max"
+    );
+    for d in found.iter() {
+        println!(
+            "{} \x1b[38;5;208m{:>#12x} {:>#12x}\x1b[0m {:>12} {:>+8.3} {:>8.3}",
+            if d.mean > 0.0 { "▲" } else { "▼" },
+            d.start,
+            d.end,
+            d.len(),
+            d.mean,
+            d.max
+        );
+    }
+    Ok(())
+}
+
+/// Draw the map of a saved profile or csv output
+fn render(file: &str, save_profile: Option<&str>) -> Result<(), Error> {
+    let data = std::fs::read(file).map_err(|e| {
+        Error::Io(io::Error::new(
+            e.kind(),
+            format!("Opening {} failed with: {}", file, e),
+        ))
+    })?;
+    let profile = if data.starts_with(Profile::MAGIC) {
+        Profile::read_from(&data[..])
+    } else {
+        Profile::from_csv(&data[..], file)
+    }
+    .map_err(|e| match e {
+        Error::UnsupportedFormat(msg) => {
+            Error::UnsupportedFormat(format!("{} can't be drawn: {}", file, msg))
         }
+        e => e,
+    })?;
 
-        chunknum += 1;
+    eprintln!(
+        "*\x1b[38;5;10mDrawing the profile of {}\x1b[0m",
+        profile.source
+    );
+    if let Some(size) = profile.size {
+        eprintln!("*\x1b[38;5;10mSize {}bytes\x1b[0m", size);
     }
-    if chunknum > 0 {
-      print!(" \x1b[38;5;208m{:#04x}\x1b[0m", (addr - (80 * chunksize) + ((chunknum % 80) * chunksize)));
+    if let Some(hash) = &profile.hash {
+        let hex: String = hash.iter().map(|b| format!("{:02x}", b)).collect();
+        eprintln!("*\x1b[38;5;10mSHA-256 {}\x1b[0m", hex);
+    }
+    eprintln!("*\x1b[38;5;10mValues: {}\x1b[0m", profile.metric);
+    if let Some(path) = save_profile {
+        save(&profile, path)?;
+        eprintln!("*\x1b[38;5;10mSaved the profile to {}\x1b[0m", path);
+    }
+
+    let label = if profile.chunk_size == profile.stride {
+        format!("blksize={}B", profile.chunk_size)
+    } else {
+        format!("window={}B step={}B", profile.chunk_size, profile.stride)
+    };
+    let mut out = Map {
+        stride: profile.stride,
+        label,
+        blocks: 0,
+        colouring: Colouring::Entropy,
+        uniformity: None,
+        hits: Vec::new(),
+        boundaries: Vec::new(),
+        elf: None,
+    };
+    out.start();
+    for value in profile.values.iter() {
+        out.record(0, &Chunk::of_value(*value));
+    }
+    out.finish(None, &[]);
+    Ok(())
+}
+
+/// Write a profile to a new file
+fn save(profile: &Profile, path: &str) -> Result<(), Error> {
+    let context = |e: io::Error| {
+        Error::Io(io::Error::new(
+            e.kind(),
+            format!("Writing the profile {} failed with: {}", path, e),
+        ))
+    };
+    let file = File::create(path).map_err(context)?;
+    profile
+        .write_to(io::BufWriter::new(file))
+        .map_err(|e| match e {
+            Error::Io(e) => context(e),
+            e => e,
+        })
+}
+
+/// Passes the input through, feeding every byte consumed into a SHA-256 if there is one
+struct Hashing<'a> {
+    inner: &'a mut dyn BufRead,
+    hasher: Option<&'a mut Sha256>,
+}
+
+impl Hashing<'_> {
+    /// Hash the rest of the input, e.g. the trailing partial chunk a scan drops
+    fn finish(&mut self) -> Result<(), Error> {
+        if self.hasher.is_some() {
+            io::copy(self, &mut io::sink()).map_err(reading)?;
+        }
+        Ok(())
+    }
+}
+
+impl Read for Hashing<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(h) = self.hasher.as_mut() {
+            h.update(&buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+impl BufRead for Hashing<'_> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if let Some(h) = self.hasher.as_mut() {
+            // the bytes are still buffered, so this doesn't read
+            if let Ok(buf) = self.inner.fill_buf() {
+                h.update(&buf[..amt]);
+            }
+        }
+        self.inner.consume(amt);
+    }
+}
+
+/// List the regions of similar entropy in the input
+fn regions(
+    file: &str,
+    bytes: u32,
+    metric: Metric,
+    estimator: Estimator,
+    penalty: f64,
+    threads: Option<usize>,
+    csv: bool,
+) -> Result<(), Error> {
+    if bytes == 0 {
+        return Err(Error::ZeroLength("chunk size (--bytes)"));
+    }
+    if estimator != Estimator::PlugIn && metric != Metric::Shannon {
+        return Err(Error::InvalidArgument(
+            "--estimator only applies to the Shannon entropy, not to --metric".to_string(),
+        ));
+    }
+    if penalty.is_nan() || penalty < 0.0 {
+        return Err(Error::InvalidArgument(
+            "--penalty must not be negative".to_string(),
+        ));
+    }
+    let mut r = open(file)?;
+    let threads = workers(threads);
+    let measure = Measure {
+        metric,
+        estimator,
+        symbol: Symbol::Byte,
+        ent: false,
+        markov: None,
+        uniformity: None,
+        lz: false,
+        divergence: false,
+        classify: false,
+        histogram: true,
+        bytes: false,
+        elf: false,
+    };
+
+    let mut entropies = Vec::new();
+    let mut histograms = Vec::new();
+    let mut sink = |_offset: u64, chunk: Chunk| {
+        entropies.push(chunk.value);
+        histograms.extend(chunk.histogram);
+        Ok(())
+    };
+    if seekable(file) && threads > 1 {
+        parallel(file, bytes as usize, threads, measure, None, &mut sink)?;
+    } else {
+        chunked(&mut r, bytes as usize, measure, &mut sink)?;
+    }
+
+    let regions = segment(&entropies, bytes as u64, penalty)?;
+    // the histogram of a region is the sum of the ones of its chunks
+    let class = |region: &Region| -> Result<Class, Error> {
+        let mut h = Histogram::new();
+        let chunks = (region.start / bytes as u64) as usize..(region.end / bytes as u64) as usize;
+        for c in histograms[chunks].iter() {
+            h.merge(c)?;
+        }
+        Ok(Class::of(&h))
+    };
+    if csv {
+        println!("\"start\";\"end\";\"entropy\";\"class\"");
+        for region in regions.iter() {
+            println!(
+                "{};{};{:.6};{}",
+                region.start,
+                region.end,
+                region.mean,
+                class(region)?
+            );
+        }
+    } else {
+        println!(
+            "\x1b[38;5;11m  {:>12} {:>12} {:>12} {:>8}  class\x1b[0m",
+            "start", "end", "length", "entropy"
+        );
+        for region in regions.iter() {
+            let class = class(region)?;
+            println!(
+                "\x1b[38;5;{}m█\x1b[0m \x1b[38;5;208m{:>#12x} {:>#12x}\x1b[0m {:>12} {:>8.3}  \x1b[38;5;{}m{}\x1b[0m",
+                entropy_colour(region.mean),
+                region.start,
+                region.end,
+                region.len(),
+                region.mean,
+                class_colour(class),
+                class.description()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// A buffered reader over the file or stdin for '-'
+fn open(file: &str) -> Result<Box<dyn BufRead>, Error> {
+    if file.eq("-") {
+        let s = io::stdin();
+        eprintln!("* Using stdin for input");
+        let r = BufReader::with_capacity(2048usize, s);
+        Ok(Box::new(r))
+    } else {
+        let s = File::open(file).map_err(|e| {
+            Error::Io(io::Error::new(
+                e.kind(),
+                format!("Opening {} failed with: {}", file, e),
+            ))
+        })?;
+        eprintln!("*\x1b[38;5;10mUsing {} for input\x1b[0m", file);
+        let r = BufReader::with_capacity(2048usize, s);
+        Ok(Box::new(r))
+    }
+}
+
+/// Adds what failed to an error while reading the input
+fn reading(e: io::Error) -> Error {
+    Error::Io(io::Error::new(
+        e.kind(),
+        format!("Reading input failed with: {}", e),
+    ))
+}
+
+/// The number of workers, one per CPU unless given
+fn workers(threads: Option<usize>) -> usize {
+    threads.unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()))
+}
+
+/// Whether the input can be split up front, which pipes and devices can't
+fn seekable(file: &str) -> bool {
+    file != "-"
+        && File::open(file)
+            .and_then(|f| f.metadata())
+            .is_ok_and(|m| m.is_file())
+}
+
+/// Consecutive, disjoint chunks. A trailing partial chunk isn't passed to `sink`, but returned.
+fn chunked(
+    r: &mut dyn BufRead,
+    chunksize: usize,
+    measure: Measure,
+    sink: &mut dyn FnMut(u64, Chunk) -> Result<(), Error>,
+) -> Result<Vec<u8>, Error> {
+    let mut buf = vec![0u8; chunksize];
+    let mut offset = 0;
+
+    loop {
+        // unlike read_exact, this keeps the bytes read before the end of the input
+        let mut n = 0;
+        while n < chunksize {
+            match r.read(&mut buf[n..]) {
+                Ok(0) => {
+                    buf.truncate(n);
+                    return Ok(buf);
+                }
+                Ok(read) => n += read,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(reading(e)),
+            }
+        }
+        sink(offset, measure.chunk(&buf)?)?;
+        offset += chunksize as u64;
+    }
+}
+
+/// Same as [chunked], but the chunks are spread over `threads` workers which read the file on
+/// their own. If given, `hasher` is fed the whole file in order.
+fn parallel(
+    path: &str,
+    chunksize: usize,
+    threads: usize,
+    measure: Measure,
+    mut hasher: Option<&mut Sha256>,
+    sink: &mut dyn FnMut(u64, Chunk) -> Result<(), Error>,
+) -> Result<Vec<u8>, Error> {
+    let keep = hasher.is_some();
+    let scan = ParallelScan::file(path, chunksize, threads, move |c| {
+        (measure.chunk(c), if keep { Some(c.to_vec()) } else { None })
+    })?;
+
+    let mut end = 0;
+    for result in scan {
+        let (offset, (chunk, bytes)) = result.map_err(|e| match e {
+            Error::Io(e) => reading(e),
+            e => e,
+        })?;
+        if let (Some(h), Some(bytes)) = (hasher.as_mut(), bytes) {
+            h.update(&bytes);
+        }
+        end = offset + chunksize as u64;
+        sink(offset, chunk?)?;
+    }
+    // the workers drop the trailing partial chunk
+    let mut tail = Vec::new();
+    File::open(path)
+        .and_then(|mut f| f.seek(SeekFrom::Start(end)).map(|_| f))
+        .and_then(|mut f| f.read_to_end(&mut tail))
+        .map_err(reading)?;
+    if let Some(h) = hasher {
+        h.update(&tail);
+    }
+    Ok(tail)
+}
+
+/// Entropy of a window sliding over the input, reported every `step` bytes once the window is full.
+fn rolling(
+    r: &mut dyn BufRead,
+    window: usize,
+    step: usize,
+    measure: Measure,
+    sink: &mut dyn FnMut(u64, Chunk) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let mut c = RollingCalculator::new(window)?;
+    let mut pos = 0u64;
+
+    loop {
+        let n = match r.fill_buf() {
+            Ok([]) => break,
+            Ok(buf) => {
+                for byte in buf {
+                    c.push(*byte);
+                    pos += 1;
+                    if c.is_full() && (pos - window as u64).is_multiple_of(step as u64) {
+                        // only the plug-in shannon entropy can be updated incrementally
+                        let value = match (measure.metric, measure.estimator) {
+                            (Metric::Shannon, Estimator::PlugIn) => c.entropy(),
+                            _ => measure.value(&c.histogram()),
+                        };
+                        sink(pos - window as u64, Chunk::of_value(value))?;
+                    }
+                }
+                buf.len()
+            }
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(reading(e)),
+        };
+        r.consume(n);
+    }
+    Ok(())
+}
+
+/// List the signatures no chunk was recorded for on stderr
+fn unassigned(hits: &[Hit]) {
+    if !hits.is_empty() {
+        let hits: Vec<String> = hits.iter().map(|h| h.to_string()).collect();
+        eprintln!("No chunk to list the signatures under: {}", hits.join(" "));
+    }
+}
+
+/// Print the two-line column index (01 to 50 in hex) above and below the map
+fn ruler(label: &str) {
+    for col in 1..=COLUMNS {
+        print!("\x1b[38;5;11m{:x}\x1b[0m", col / 16);
     }
     println!();
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m5\x1b[0m");
-    println!();
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m5\x1b[0m");
-    print!("\x1b[38;5;11m6\x1b[0m");
-    print!("\x1b[38;5;11m7\x1b[0m");
-    print!("\x1b[38;5;11m8\x1b[0m");
-    print!("\x1b[38;5;11m9\x1b[0m");
-    print!("\x1b[38;5;11ma\x1b[0m");
-    print!("\x1b[38;5;11mb\x1b[0m");
-    print!("\x1b[38;5;11mc\x1b[0m");
-    print!("\x1b[38;5;11md\x1b[0m");
-    print!("\x1b[38;5;11me\x1b[0m");
-    print!("\x1b[38;5;11mf\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m5\x1b[0m");
-    print!("\x1b[38;5;11m6\x1b[0m");
-    print!("\x1b[38;5;11m7\x1b[0m");
-    print!("\x1b[38;5;11m8\x1b[0m");
-    print!("\x1b[38;5;11m9\x1b[0m");
-    print!("\x1b[38;5;11ma\x1b[0m");
-    print!("\x1b[38;5;11mb\x1b[0m");
-    print!("\x1b[38;5;11mc\x1b[0m");
-    print!("\x1b[38;5;11md\x1b[0m");
-    print!("\x1b[38;5;11me\x1b[0m");
-    print!("\x1b[38;5;11mf\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m5\x1b[0m");
-    print!("\x1b[38;5;11m6\x1b[0m");
-    print!("\x1b[38;5;11m7\x1b[0m");
-    print!("\x1b[38;5;11m8\x1b[0m");
-    print!("\x1b[38;5;11m9\x1b[0m");
-    print!("\x1b[38;5;11ma\x1b[0m");
-    print!("\x1b[38;5;11mb\x1b[0m");
-    print!("\x1b[38;5;11mc\x1b[0m");
-    print!("\x1b[38;5;11md\x1b[0m");
-    print!("\x1b[38;5;11me\x1b[0m");
-    print!("\x1b[38;5;11mf\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m5\x1b[0m");
-    print!("\x1b[38;5;11m6\x1b[0m");
-    print!("\x1b[38;5;11m7\x1b[0m");
-    print!("\x1b[38;5;11m8\x1b[0m");
-    print!("\x1b[38;5;11m9\x1b[0m");
-    print!("\x1b[38;5;11ma\x1b[0m");
-    print!("\x1b[38;5;11mb\x1b[0m");
-    print!("\x1b[38;5;11mc\x1b[0m");
-    print!("\x1b[38;5;11md\x1b[0m");
-    print!("\x1b[38;5;11me\x1b[0m");
-    print!("\x1b[38;5;11mf\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m1\x1b[0m");
-    print!("\x1b[38;5;11m2\x1b[0m");
-    print!("\x1b[38;5;11m3\x1b[0m");
-    print!("\x1b[38;5;11m4\x1b[0m");
-    print!("\x1b[38;5;11m5\x1b[0m");
-    print!("\x1b[38;5;11m6\x1b[0m");
-    print!("\x1b[38;5;11m7\x1b[0m");
-    print!("\x1b[38;5;11m8\x1b[0m");
-    print!("\x1b[38;5;11m9\x1b[0m");
-    print!("\x1b[38;5;11ma\x1b[0m");
-    print!("\x1b[38;5;11mb\x1b[0m");
-    print!("\x1b[38;5;11mc\x1b[0m");
-    print!("\x1b[38;5;11md\x1b[0m");
-    print!("\x1b[38;5;11me\x1b[0m");
-    print!("\x1b[38;5;11mf\x1b[0m");
-    print!("\x1b[38;5;11m0\x1b[0m");
-    print!("\x1b[38;5;11m blksize={}B\x1b[0m",chunksize);
-    println!();
+    for col in 1..=COLUMNS {
+        print!("\x1b[38;5;11m{:x}\x1b[0m", col % 16);
+    }
+    print!("\x1b[38;5;11m {}\x1b[0m", label);
+}
+
+/// Print a ruler under a row of the map with a `|` in every (column, names) where ELF sections
+/// start, followed by as much of the names as fits before the next one
+fn boundaries(marks: &[(usize, String)]) {
+    print!("\n\x1b[38;5;11m");
+    let mut column = 0;
+    for (i, (at, names)) in marks.iter().enumerate() {
+        let room = marks
+            .get(i + 1)
+            .map_or(usize::MAX, |(next, _)| next - at - 1);
+        let shown: String = names.chars().take(room).collect();
+        print!("{:2$}|{}", "", shown, at - column);
+        column = at + 1 + shown.chars().count();
+    }
+    print!("\x1b[0m");
+}
+
+/// Print the entropy of the sections and segments of an ELF file, marking packed ones with !
+fn elf_table(report: &ElfReport) {
+    for (title, parts) in [("section", &report.sections), ("segment", &report.segments)].iter() {
+        println!();
+        println!(
+            "\x1b[38;5;11m  {:<20} {:>12} {:>12} {:>8} class\x1b[0m",
+            title, "offset", "size", "entropy"
+        );
+        for p in parts.iter() {
+            println!(
+                "{} {:<20} \x1b[38;5;208m{:>#12x}\x1b[0m {:>12} \x1b[38;5;{}m{:>8.3}\x1b[0m {}",
+                if p.packed {
+                    "\x1b[38;5;196m!\x1b[0m"
+                } else {
+                    " "
+                },
+                p.name,
+                p.offset,
+                p.size,
+                entropy_colour(p.entropy),
+                p.entropy,
+                p.class
+            );
+        }
+    }
+    if report
+        .sections
+        .iter()
+        .chain(report.segments.iter())
+        .any(|p| p.packed)
+    {
+        println!();
+        println!("\x1b[38;5;196m!\x1b[0m packed or encrypted");
+    }
 }
 
-fn ansi_rgb_string_special(s: String, mut r: u32, mut g: u32, mut b: u32) -> String {
-  let mut ansi_code = String::from("\x1b[38;5;");
-  if r < 255 { r = 0; g = 0; b = 0; } //000
-  else if r >= 255 && r < 260 { r = 0; g = 0; b = 1; } //001
-  else if r >= 260 && r < 265 { r = 0; g = 0; b = 2; } //002
-  else if r >= 265 && r < 270 { r = 0; g = 0; b = 3; } //003
-  else if r >= 270 && r < 272 { r = 0; g = 0; b = 4; } //004
-  else if r >= 272 && r < 274 { r = 0; g = 0; b = 5; } //005
-  else if r >= 274 && r < 276 { r = 0; g = 1; b = 5; } //015
-  else if r >= 276 && r < 278 { r = 0; g = 1; b = 4; } //014
-  else if r >= 278 && r < 280 { r = 0; g = 1; b = 3; } //013 
-  else if r >= 280 && r < 285 { r = 0; g = 2; b = 4; } //024
-  else if r >= 285 && r < 290 { r = 1; g = 4; b = 5; } //145
-  else if r >= 290 && r < 292 { r = 2; g = 5; b = 5; } //255
-  else if r >= 292 && r < 294 { r = 2; g = 4; b = 5; } //245
-  else if r >= 294 && r < 296 { r = 1; g = 2; b = 4; } //124
-  else if r >= 296 && r < 298 { r = 1; g = 2; b = 5; } //125
-  else if r >= 298 && r < 300 { r = 2; g = 2; b = 5; } //225
-  else if r >= 300 && r < 305 { r = 2; g = 1; b = 5; } //215
-  else if r >= 305 && r < 310 { r = 2; g = 0; b = 5; } //205
-  else if r >= 310 && r < 312 { r = 2; g = 0; b = 4; } //204
-  else if r >= 312 && r < 314 { r = 1; g = 0; b = 4; } //104
-  else if r >= 314 && r < 316 { r = 1; g = 0; b = 3; } //103
-  else if r >= 316 && r < 318 { r = 1; g = 0; b = 2; } //102
-  else if r >= 318 && r < 320 { r = 2; g = 0; b = 3; } //203
-  else if r >= 320 && r < 322 { r = 3; g = 0; b = 4; } //304
-  else if r >= 322 && r < 324 { r = 4; g = 0; b = 5; } //405
-  else if r >= 324 && r < 326 { r = 4; g = 0; b = 4; } //404
-  else if r >= 326 && r < 328 { r = 5; g = 0; b = 5; } //505
-  else if r >= 328 && r < 330 { r = 5; g = 0; b = 4; } //504
-  else if r >= 330 && r < 334 { r = 5; g = 0; b = 3; } //503
-  else if r >= 334 && r < 340 { r = 4; g = 0; b = 3; } //403
-  else if r >= 340 && r < 342 { r = 4; g = 0; b = 2; } //402
-  else if r >= 342 && r < 344 { r = 4; g = 0; b = 1; } //401
-  else if r >= 344 && r < 346 { r = 5; g = 0; b = 2; } //502
-  else if r >= 346 && r < 350 { r = 5; g = 0; b = 1; } //501
-  else if r >= 350 && r < 355 { r = 5; g = 0; b = 0; } //500
-  else if r >= 355 && r < 360 { r = 4; g = 0; b = 0; } //400
-  else if r >= 360 && r < 365 { r = 3; g = 0; b = 0; } //300
-  else if r > 365 { r = 2; g = 0; b = 0; } //200
-  
-  let color = 16 + (r * 36) + (g * 6) + b;
-  ansi_code.push_str(&color.to_string()[..]);
-  ansi_code.push_str("m");
-  ansi_code.push_str(&s);
-  ansi_code.push_str("\x1b[0m");
-  return ansi_code; 
-}
-
-fn ansi_rgb_string(s: String, r: u32, g: u32, b: u32) -> String {
-  if r > 6 || g > 6 || b > 6 { return s; }
-  let mut ansi_code = String::from("\x1b[48;5;");
-  let color = 16 + (r * 36) + (g * 6) + b;
-  ansi_code.push_str(&color.to_string()[..]);
-  ansi_code.push_str("m");
-  ansi_code.push_str(&s);
-  ansi_code.push_str("\x1b[0m");
-  return ansi_code;
+/// Print the offset at the end of a row
+fn address(addr: u64) {
+    print!(" \x1b[38;5;208m{:#04x}\x1b[0m", addr);
 }
 
+/// The ANSI 256 colour code of the given coordinates in the 6x6x6 colour cube
+fn cube(rgb: [u32; 3]) -> u32 {
+    16 + rgb[0] * 36 + rgb[1] * 6 + rgb[2]
+}
+
+/// The ANSI 256 colour code used to draw the given class
+fn class_colour(class: Class) -> u32 {
+    match class {
+        Class::Zero => 238,
+        Class::Erased => 255,
+        Class::Text => 34,
+        Class::Code => 33,
+        Class::Compressed => 208,
+        Class::Random => 196,
+        Class::Other => 134,
+    }
+}
+
+/// The ANSI 256 colour code used to draw the given entropy
+fn entropy_colour(entropy: f64) -> u32 {
+    // scale entropy to bits (i.e. value/8)
+    // i.e. perfectly uniform data would have an entropy of 1 (i.e. 8bits/byte)
+    let h = (240.0 + entropy / 8.0 * 120.0) as u32;
+    let rgb = PALETTE
+        .iter()
+        .find(|(bound, _)| h < *bound)
+        .map(|(_, rgb)| *rgb)
+        .unwrap_or([2, 0, 0]);
+    cube(rgb)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{chunked, parallel, Chunk, Measure, Output, Signatures, RULES_HELP};
+    use std::collections::VecDeque;
+    use std::io::Cursor;
+    use tropy::{
+        EntCalculator, EntReport, Estimator, Hit, Metric, RuleSet, SignatureScanner, Symbol,
+    };
+
+    /// Nothing but the plug-in Shannon entropy
+    fn shannon() -> Measure {
+        Measure {
+            metric: Metric::Shannon,
+            estimator: Estimator::PlugIn,
+            symbol: Symbol::Byte,
+            ent: false,
+            markov: None,
+            uniformity: None,
+            lz: false,
+            divergence: false,
+            classify: false,
+            histogram: false,
+            bytes: false,
+            elf: false,
+        }
+    }
+
+    /// The hits listed per chunk
+    #[derive(Default)]
+    struct Listed {
+        chunks: Vec<(u64, Vec<String>)>,
+        unassigned: Vec<String>,
+    }
+
+    impl Output for Listed {
+        fn start(&mut self) {}
+
+        fn record(&mut self, offset: u64, chunk: &Chunk) {
+            let hits = chunk.signatures.iter().flatten().map(|h| h.to_string());
+            self.chunks.push((offset, hits.collect()));
+        }
+
+        fn finish(&mut self, _total: Option<&EntReport>, hits: &[Hit]) {
+            self.unassigned = hits.iter().map(|h| h.to_string()).collect();
+        }
+    }
+
+    /// Scan for signatures the way `run` does
+    fn signatures(data: &[u8], bytes: usize, scanner: SignatureScanner) -> Listed {
+        let mut out = Listed::default();
+        let mut s = Signatures {
+            scanner,
+            pending: VecDeque::new(),
+            hits: VecDeque::new(),
+        };
+        let mut sink = |offset: u64, chunk: Chunk| {
+            s.push(offset, chunk, &mut out);
+            Ok(())
+        };
+        let measure = Measure {
+            bytes: true,
+            ..shannon()
+        };
+        let tail = chunked(&mut Cursor::new(data), bytes, measure, &mut sink).unwrap();
+        let unassigned = s.finish(&tail, &mut out);
+        out.finish(None, &unassigned);
+        out
+    }
+
+    #[test]
+    fn signatures_in_tail() {
+        let gzip = [0x1f, 0x8b, 0x08, 0x00];
+        let mut data = vec![0u8; 3000];
+        data[0x64..0x68].copy_from_slice(&gzip);
+        data[0x9c4..0x9c8].copy_from_slice(&gzip);
+        // the last chunk takes the hits of the trailing partial one
+        let listed = signatures(&data, 1024, SignatureScanner::new());
+        assert_eq!(
+            listed.chunks,
+            vec![
+                (0, vec!["gzip@0x64".to_string()]),
+                (1024, vec!["gzip@0x9c4".to_string()])
+            ]
+        );
+        assert!(listed.unassigned.is_empty());
+
+        // no chunk at all
+        let listed = signatures(&data[..1500], 2048, SignatureScanner::new());
+        assert!(listed.chunks.is_empty());
+        assert_eq!(listed.unassigned, vec!["gzip@0x64"]);
+    }
+
+    #[test]
+    fn rules_help() {
+        let example = RULES_HELP.lines().last().unwrap();
+        let rules: RuleSet = example.parse().unwrap();
+        assert_eq!(rules.rules()[0].name, "acme-fw");
+    }
+
+    #[test]
+    fn rules_in_tail() {
+        let rules: RuleSet = "acme 41 43 4d 45 entropy>=7/1k\nend 11 22 33"
+            .parse()
+            .unwrap();
+        let mut data = vec![0u8; 3000];
+        data[1900..1904].copy_from_slice(b"ACME");
+        // xorshift, the entropy condition needs the random bytes up to the end of the input
+        let mut x = 0x2545_f491_4f6c_dd1du64;
+        for b in data[1904..].iter_mut() {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            *b = (x >> 32) as u8;
+        }
+        data[2900..2903].copy_from_slice(&[0x11, 0x22, 0x33]);
+
+        let listed = signatures(&data, 1024, SignatureScanner::with_rules(rules));
+        let hits: Vec<String> = listed.chunks.into_iter().flat_map(|(_, h)| h).collect();
+        assert_eq!(hits, vec!["acme@0x76c", "end@0xb54"]);
+    }
+
+    #[test]
+    fn ent_summary_covers_tail() {
+        // not a multiple of the chunk size
+        let data: Vec<u8> = (0u32..5000)
+            .map(|i| (i.wrapping_mul(2654435761) >> (i % 24)) as u8)
+            .collect();
+        let measure = Measure {
+            ent: true,
+            ..shannon()
+        };
+        let whole = EntReport::of(&data);
+        let summary = |tail: Vec<u8>, chunks: Vec<EntCalculator>| {
+            let mut total = EntCalculator::new();
+            chunks.iter().for_each(|c| total.merge(c).unwrap());
+            total.update(&tail).unwrap();
+            total.report()
+        };
+
+        let mut chunks = Vec::new();
+        let mut sink = |_offset: u64, chunk: Chunk| {
+            chunks.extend(chunk.ent);
+            Ok(())
+        };
+        let tail = chunked(&mut Cursor::new(&data), 1024, measure, &mut sink).unwrap();
+        assert_eq!(tail, &data[4096..]);
+        let sequential = summary(tail, chunks);
+        assert_eq!(sequential.bytes, 5000);
+        assert_eq!(sequential.chi_square, whole.chi_square);
+        assert_eq!(sequential.mean, whole.mean);
+
+        let path = std::env::temp_dir().join(format!("tropy-tail-{}", std::process::id()));
+        std::fs::write(&path, &data).unwrap();
+        let mut chunks = Vec::new();
+        let mut sink = |_offset: u64, chunk: Chunk| {
+            chunks.extend(chunk.ent);
+            Ok(())
+        };
+        let tail = parallel(path.to_str().unwrap(), 1024, 3, measure, None, &mut sink);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(summary(tail.unwrap(), chunks), sequential);
+    }
+}
@@ -0,0 +1,319 @@
+use std::io::{ErrorKind, Read};
+
+use crate::{Error, Histogram, Metric};
+
+/// What [ChunkedEntropy] does with a chunk cut short by the end of the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LastChunk {
+    /// Skip it, so every chunk has the full size
+    #[default]
+    Drop,
+    /// Yield it with the bytes there are
+    Include,
+    /// Fill it up to the full size with the given byte before calculating the entropy
+    Pad(u8),
+}
+
+/// Walks any reader in chunks and yields `(offset, len, entropy)` for each.
+///
+/// Chunks start every `stride` bytes, which defaults to the chunk size. A smaller stride makes
+/// consecutive chunks overlap, a larger one skips the bytes in between. `len` is the number of
+/// input bytes in the chunk, which is only less than the chunk size for the last one, see
+/// [LastChunk]. A short chunk is only yielded if it holds bytes no earlier chunk covered.
+///
+/// Read errors end the iteration after being yielded; interrupted reads are retried.
+///
+/// # Example
+///```
+/// use tropy::{ChunkedEntropy, LastChunk};
+///
+/// let data = [0u8, 1, 2, 3, 0, 0, 7];
+/// let chunks: Vec<(u64, usize, f64)> = ChunkedEntropy::new(&data[..], 4)
+///     .unwrap()
+///     .with_last(LastChunk::Include)
+///     .map(|r| r.unwrap())
+///     .collect();
+///
+/// assert_eq!(chunks, vec![(0, 4, 2.0), (4, 3, 0.9182958340544896)]);
+///```
+pub struct ChunkedEntropy<R: Read> {
+    reader: R,
+    chunker: Chunker,
+}
+
+impl<R: Read> ChunkedEntropy<R> {
+    /// Walk `reader` in disjoint chunks of `chunksize` bytes, calculating the Shannon entropy and
+    /// dropping a trailing partial chunk.
+    ///
+    /// Fails with [Error::ZeroLength] if `chunksize` is zero.
+    pub fn new(reader: R, chunksize: usize) -> Result<Self, Error> {
+        Ok(ChunkedEntropy {
+            reader,
+            chunker: Chunker::new(chunksize)?,
+        })
+    }
+
+    /// Start a chunk every `stride` bytes.
+    ///
+    /// Fails with [Error::ZeroLength] if `stride` is zero.
+    pub fn with_stride(mut self, stride: usize) -> Result<Self, Error> {
+        self.chunker.set_stride(stride)?;
+        Ok(self)
+    }
+
+    /// Set what happens to a chunk cut short by the end of the input.
+    pub fn with_last(mut self, last: LastChunk) -> Self {
+        self.chunker.last = last;
+        self
+    }
+
+    /// Calculate the given measure instead of the Shannon entropy.
+    pub fn with_metric(mut self, metric: Metric) -> Self {
+        self.chunker.metric = metric;
+        self
+    }
+}
+
+impl<R: Read> Iterator for ChunkedEntropy<R> {
+    type Item = Result<(u64, usize, f64), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut scratch = [0u8; SCRATCH];
+        loop {
+            if self.chunker.done {
+                return None;
+            }
+            let wanted = self.chunker.wanted();
+            if wanted == 0 {
+                return self.chunker.emit(false).map(Ok);
+            }
+            let n = wanted.min(scratch.len());
+            match self.reader.read(&mut scratch[..n]) {
+                Ok(0) => return self.chunker.emit(true).map(Ok),
+                Ok(n) => self.chunker.consume(&scratch[..n]),
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.chunker.done = true;
+                    return Some(Err(e.into()));
+                }
+            }
+        }
+    }
+}
+
+/// Bytes read at once
+pub(crate) const SCRATCH: usize = 4096;
+
+/// The chunking shared by the blocking and the async walker, which only differ in how they read
+pub(crate) struct Chunker {
+    chunksize: usize,
+    stride: usize,
+    pub(crate) last: LastChunk,
+    pub(crate) metric: Metric,
+    // the bytes of the current chunk read so far, starting at `offset`
+    buf: Vec<u8>,
+    offset: u64,
+    // bytes between the end of the current chunk and the start of the next one, if the stride
+    // exceeds the chunk size
+    skip: usize,
+    // end of the last chunk yielded
+    covered: u64,
+    pub(crate) done: bool,
+}
+
+impl Chunker {
+    pub(crate) fn new(chunksize: usize) -> Result<Self, Error> {
+        if chunksize == 0 {
+            return Err(Error::ZeroLength("chunk size"));
+        }
+        Ok(Chunker {
+            chunksize,
+            stride: chunksize,
+            last: LastChunk::Drop,
+            metric: Metric::Shannon,
+            buf: Vec::with_capacity(chunksize),
+            offset: 0,
+            skip: 0,
+            covered: 0,
+            done: false,
+        })
+    }
+
+    pub(crate) fn set_stride(&mut self, stride: usize) -> Result<(), Error> {
+        if stride == 0 {
+            return Err(Error::ZeroLength("stride"));
+        }
+        self.stride = stride;
+        Ok(())
+    }
+
+    /// The number of bytes to read before the current chunk is complete, 0 if it is
+    pub(crate) fn wanted(&self) -> usize {
+        if self.skip > 0 {
+            self.skip
+        } else {
+            self.chunksize - self.buf.len()
+        }
+    }
+
+    /// Take up to [Chunker::wanted] bytes read from the input
+    pub(crate) fn consume(&mut self, bytes: &[u8]) {
+        if self.skip > 0 {
+            self.skip -= bytes.len();
+            self.offset += bytes.len() as u64;
+        } else {
+            self.buf.extend_from_slice(bytes);
+        }
+    }
+
+    /// Finish the current chunk once it is complete or the input `ended`
+    pub(crate) fn emit(&mut self, ended: bool) -> Option<(u64, usize, f64)> {
+        let offset = self.offset;
+        let len = self.buf.len();
+        let end = offset + len as u64;
+        if ended {
+            self.done = true;
+            if len == 0 || end <= self.covered {
+                return None;
+            }
+            match self.last {
+                LastChunk::Drop => return None,
+                LastChunk::Include => {}
+                LastChunk::Pad(byte) => self.buf.resize(self.chunksize, byte),
+            }
+        }
+        self.covered = end;
+        let hist = Histogram::from(&self.buf[..]);
+
+        if !self.done {
+            // keep the overlap with the next chunk, if any
+            let keep = self.chunksize.saturating_sub(self.stride);
+            self.buf.drain(..self.chunksize - keep);
+            self.offset += (self.chunksize - keep) as u64;
+            self.skip = self.stride.saturating_sub(self.chunksize);
+        }
+
+        Some((offset, len, self.metric.of(&hist)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_util::noise;
+    use crate::{slice_entropy, ChunkedEntropy, Error, LastChunk, Metric};
+    use std::io;
+    use std::io::Read;
+
+    fn collect<R: Read>(c: ChunkedEntropy<R>) -> Vec<(u64, usize, f64)> {
+        c.map(|r| r.unwrap()).collect()
+    }
+
+    #[test]
+    fn last_chunk() {
+        let data = noise(1000);
+        let dropped = collect(ChunkedEntropy::new(&data[..], 300).unwrap());
+        assert_eq!(
+            dropped,
+            vec![
+                (0, 300, slice_entropy(&data[..300])),
+                (300, 300, slice_entropy(&data[300..600])),
+                (600, 300, slice_entropy(&data[600..900])),
+            ]
+        );
+
+        let included = collect(
+            ChunkedEntropy::new(&data[..], 300)
+                .unwrap()
+                .with_last(LastChunk::Include),
+        );
+        assert_eq!(included.len(), 4);
+        assert_eq!(included[3], (900, 100, slice_entropy(&data[900..])));
+
+        let padded = collect(
+            ChunkedEntropy::new(&data[..], 300)
+                .unwrap()
+                .with_last(LastChunk::Pad(0)),
+        );
+        let tail = [&data[900..], &[0u8; 200][..]].concat();
+        assert_eq!(padded[3], (900, 100, slice_entropy(&tail)));
+
+        // nothing left over
+        let exact = ChunkedEntropy::new(&data[..], 250)
+            .unwrap()
+            .with_last(LastChunk::Include);
+        assert_eq!(collect(exact).len(), 4);
+        let empty = ChunkedEntropy::new(&[][..], 16)
+            .unwrap()
+            .with_last(LastChunk::Include);
+        assert!(collect(empty).is_empty());
+    }
+
+    #[test]
+    fn stride() {
+        let data = noise(1000);
+        let overlapping = collect(
+            ChunkedEntropy::new(&data[..], 100)
+                .unwrap()
+                .with_stride(40)
+                .unwrap()
+                .with_last(LastChunk::Include),
+        );
+        // full chunks start at 0, 40, ..., 880 and the short one at 920 covers the last 20 bytes
+        assert_eq!(overlapping.len(), 24);
+        for (offset, len, entropy) in overlapping.iter() {
+            let chunk = &data[*offset as usize..*offset as usize + len];
+            assert_eq!(*entropy, slice_entropy(chunk));
+        }
+        assert_eq!((overlapping[23].0, overlapping[23].1), (920, 80));
+
+        // the last full chunk ends with the input, so there is no short one
+        let aligned = ChunkedEntropy::new(&data[..], 100)
+            .unwrap()
+            .with_stride(30)
+            .unwrap()
+            .with_last(LastChunk::Include);
+        assert_eq!(collect(aligned).len(), 31);
+
+        let gaps = collect(
+            ChunkedEntropy::new(&data[..], 100)
+                .unwrap()
+                .with_stride(350)
+                .unwrap()
+                .with_last(LastChunk::Include),
+        );
+        let offsets: Vec<(u64, usize)> = gaps.iter().map(|(o, l, _)| (*o, *l)).collect();
+        assert_eq!(offsets, vec![(0, 100), (350, 100), (700, 100)]);
+        assert_eq!(gaps[1].2, slice_entropy(&data[350..450]));
+
+        let min = collect(
+            ChunkedEntropy::new(&data[..], 500)
+                .unwrap()
+                .with_metric(Metric::Min),
+        );
+        assert!(min[0].2 < slice_entropy(&data[..500]));
+    }
+
+    struct Failing;
+
+    impl Read for Failing {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("broken"))
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            ChunkedEntropy::new(&[0u8][..], 0),
+            Err(Error::ZeroLength(_))
+        ));
+        assert!(ChunkedEntropy::new(&[0u8][..], 1)
+            .unwrap()
+            .with_stride(0)
+            .is_err());
+
+        let mut c = ChunkedEntropy::new(Failing, 16).unwrap();
+        assert!(matches!(c.next(), Some(Err(Error::Io(_)))));
+        assert!(c.next().is_none());
+    }
+}
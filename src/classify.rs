@@ -0,0 +1,217 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::ent::chi_square_p_value;
+use crate::{Error, Estimator, Histogram};
+
+/// Share of the bytes which must be the fill byte for padding or erased flash
+const FILL: f64 = 0.95;
+/// Share of printable ASCII and whitespace for ASCII text
+const ASCII: f64 = 0.95;
+/// Share of printable ASCII, whitespace and the bytes of multi-byte sequences for UTF-8 text
+const UTF8: f64 = 0.98;
+/// Entropy from which data counts as compressed or random, after the sample size correction
+const HIGH_ENTROPY: f64 = 7.2;
+/// Entropy from which data counts as machine code, after the sample size correction
+const CODE_ENTROPY: f64 = 4.5;
+/// Share of 0x00 bytes machine code needs on top of its entropy. The immediates, displacements
+/// and alignment padding of x86 and ARM instructions hold about one in ten, random bytes one in
+/// 256
+const CODE_ZEROS: f64 = 0.03;
+/// Chi-square p-value below which high entropy data is too uneven to be random
+const UNIFORM: f64 = 0.001;
+
+/// What a chunk or region of a firmware image or file most likely contains.
+///
+/// The classification uses only the byte histogram: the share of fill bytes, text characters and
+/// zeros, the entropy and the chi-square test against uniformly random bytes. This makes it cheap and
+/// lets the histograms of chunks be merged to classify a whole region, but it is a heuristic:
+/// e.g. a table of small integers may pass as code.
+///
+/// # Example
+///```
+/// use tropy::{classify, Class};
+///
+/// assert_eq!(classify(&[0xffu8; 1024]), Class::Erased);
+/// assert_eq!(classify("Nothing to see here.\n".repeat(50).as_bytes()), Class::Text);
+///```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Class {
+    /// Zero bytes, e.g. padding or uninitialised memory
+    Zero,
+    /// 0xFF bytes, the state of erased NOR and NAND flash
+    Erased,
+    /// ASCII or UTF-8 text
+    Text,
+    /// Medium entropy with the zero bytes of instruction operands, typical for machine code
+    Code,
+    /// High entropy, but detectably non-uniform, e.g. deflate or LZMA streams
+    Compressed,
+    /// High entropy indistinguishable from random bytes, e.g. ciphertext or keys
+    Random,
+    /// Low entropy binary data, e.g. tables or sparse structures
+    Other,
+}
+
+impl Class {
+    /// All classes, in the order of the legend.
+    pub const ALL: [Class; 7] = [
+        Class::Zero,
+        Class::Erased,
+        Class::Text,
+        Class::Code,
+        Class::Compressed,
+        Class::Random,
+        Class::Other,
+    ];
+
+    /// Classify the bytes counted by a histogram.
+    ///
+    /// An empty histogram is classified as [Class::Zero].
+    pub fn of(hist: &Histogram) -> Class {
+        if hist.is_empty() {
+            return Class::Zero;
+        }
+        let n = hist.total() as f64;
+        let share = |bytes: &mut dyn Iterator<Item = u8>| {
+            bytes.map(|b| hist.count(b)).sum::<u64>() as f64 / n
+        };
+
+        if share(&mut std::iter::once(0x00)) >= FILL {
+            return Class::Zero;
+        }
+        if share(&mut std::iter::once(0xff)) >= FILL {
+            return Class::Erased;
+        }
+        let ascii = share(&mut (0x20..=0x7e).chain(vec![b'\t', b'\n', b'\r']));
+        // every lead byte of a multi-byte sequence is followed by at least one continuation byte
+        let continuation = share(&mut (0x80..=0xbf));
+        let lead = share(&mut (0xc2..=0xf4));
+        if ascii >= ASCII || (ascii + continuation + lead >= UTF8 && continuation >= lead) {
+            return Class::Text;
+        }
+
+        // the plug-in estimate falls short by about (K - 1) / (2n ln 2) bits for K distinct bytes
+        // (Miller-Madow), so short chunks of random data would not reach the thresholds otherwise
+        let entropy = Estimator::MillerMadow.of(hist);
+        if entropy >= HIGH_ENTROPY {
+            if chi_square_p_value(hist.chi_square(), 255.0) >= UNIFORM {
+                Class::Random
+            } else {
+                Class::Compressed
+            }
+        } else if entropy >= CODE_ENTROPY && share(&mut std::iter::once(0x00)) >= CODE_ZEROS {
+            Class::Code
+        } else {
+            Class::Other
+        }
+    }
+
+    /// A one-line description for legends.
+    pub fn description(&self) -> &'static str {
+        match self {
+            Class::Zero => "zero padding",
+            Class::Erased => "erased flash (0xFF)",
+            Class::Text => "ASCII/UTF-8 text",
+            Class::Code => "machine code",
+            Class::Compressed => "compressed",
+            Class::Random => "encrypted or random",
+            Class::Other => "other low entropy data",
+        }
+    }
+}
+
+impl Display for Class {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name = match self {
+            Class::Zero => "zero",
+            Class::Erased => "erased",
+            Class::Text => "text",
+            Class::Code => "code",
+            Class::Compressed => "compressed",
+            Class::Random => "random",
+            Class::Other => "other",
+        };
+        f.pad(name)
+    }
+}
+
+impl FromStr for Class {
+    type Err = Error;
+
+    /// Parses the names written by [Display].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Class::ALL
+            .iter()
+            .find(|c| c.to_string() == s)
+            .cloned()
+            .ok_or_else(|| Error::UnsupportedFormat(format!("Unknown class {:?}", s)))
+    }
+}
+
+/// Convenience method: Counts the given input and classifies it, see [Class::of].
+pub fn classify(input: &[u8]) -> Class {
+    Class::of(&Histogram::from(input))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_util::noise;
+    use crate::{classify, Class};
+
+    #[test]
+    fn classes() {
+        let mut padded = vec![0u8; 1000];
+        padded[..20].copy_from_slice(&noise(20));
+        assert_eq!(classify(&padded), Class::Zero);
+        assert_eq!(classify(&[0xffu8; 4096]), Class::Erased);
+
+        let text = "The quick brown fox jumps over the lazy dog.\r\n\tFin.\n".repeat(40);
+        assert_eq!(classify(text.as_bytes()), Class::Text);
+        let utf8 = "Übergrößenträger, ça va? Ελληνικά κείμενα. ".repeat(40);
+        assert_eq!(classify(utf8.as_bytes()), Class::Text);
+
+        // every bit is set with a probability of 1/4, about 6.5 bits per byte
+        let noise2 = noise(8192);
+        let code: Vec<u8> = noise2[..4096]
+            .iter()
+            .zip(&noise2[4096..])
+            .map(|(a, b)| a & b)
+            .collect();
+        assert_eq!(classify(&code), Class::Code);
+        // a tenth of the bytes are zero, as in instruction operands
+        assert_eq!(classify(&code[..64]), Class::Code);
+
+        for &n in &[256, 1024, 65536] {
+            assert_eq!(classify(&noise(n)), Class::Random, "{} bytes", n);
+        }
+
+        // high entropy, but every tenth byte is a small value
+        let compressed: Vec<u8> = noise(65536)
+            .iter()
+            .enumerate()
+            .map(|(i, b)| if i % 10 == 0 { b & 0x0f } else { *b })
+            .collect();
+        assert_eq!(classify(&compressed), Class::Compressed);
+
+        // the sample size correction must not lift short chunks, e.g. a pointer in .init_array
+        assert_eq!(classify(&[0x30, 0x11, 0x40, 0, 0, 0, 0, 0]), Class::Other);
+        // medium entropy alone isn't code, short random chunks have it too
+        assert_eq!(classify(&noise(64)), Class::Other);
+
+        let table: Vec<u8> = (0..4096u32).map(|i| (i % 5) as u8 * 3).collect();
+        assert_eq!(classify(&table), Class::Other);
+        assert_eq!(classify(&[]), Class::Zero);
+    }
+
+    #[test]
+    fn names() {
+        for class in Class::ALL.iter() {
+            assert_eq!(&class.to_string().parse::<Class>().unwrap(), class);
+        }
+        assert!("data".parse::<Class>().is_err());
+    }
+}
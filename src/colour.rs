@@ -45,11 +45,11 @@ impl Rgb {
 impl<'a> Display for RGB<'a> {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         match self {
-            RGB::Fg(c, txt) => write!(f, "\x1B[38;5;{};{};{}m{}\x1B[0m", c.0, c.1, c.2, txt),
-            RGB::Bg(c, txt) => write!(f, "\x1B[48;5;{};{};{}m{}\x1B[0m", c.0, c.1, c.2, txt),
+            RGB::Fg(c, txt) => write!(f, "\x1B[38;2;{};{};{}m{}\x1B[39m", c.0, c.1, c.2, txt),
+            RGB::Bg(c, txt) => write!(f, "\x1B[48;2;{};{};{}m{}\x1B[49m", c.0, c.1, c.2, txt),
             RGB::FgBg(fg, bg, txt) => write!(
                 f,
-                "\x1B[38;5;{};{};{}m\x1B[48;5;{};{};{}m{}\x1B[49m\x1B[39m",
+                "\x1B[38;2;{};{};{}m\x1B[48;2;{};{};{}m{}\x1B[49m\x1B[39m",
                 fg.0, fg.1, fg.2, bg.0, bg.1, bg.2, txt
             ),
         }
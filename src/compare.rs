@@ -0,0 +1,140 @@
+use crate::Error;
+
+/// A run of consecutive chunks in which two entropy profiles differ by more than a threshold, as
+/// found by [differences].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Difference {
+    /// Offset of the first byte
+    pub start: u64,
+    /// Offset after the last byte
+    pub end: u64,
+    /// Mean of the second profile minus the first over the chunks, negative if the entropy dropped
+    pub mean: f64,
+    /// The largest absolute difference of a chunk
+    pub max: f64,
+}
+
+impl Difference {
+    /// The number of bytes covered.
+    pub fn len(&self) -> u64 {
+        self.end - self.start
+    }
+
+    /// Whether the difference covers no bytes.
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+/// Find the runs of chunks at the same offsets whose entropies in `a` and `b` differ by more than
+/// `threshold` bits in the same direction.
+///
+/// Both profiles are chunked the same, with the first chunk at offset 0 and `chunk_size` bytes per
+/// chunk. Only the chunks both profiles have are compared.
+///
+/// Fails with [Error::InvalidArgument] if the threshold is negative or NaN.
+///
+/// # Example
+///```
+/// use tropy::differences;
+///
+/// let a = [7.9, 7.9, 4.0, 4.0, 0.0];
+/// let b = [7.9, 7.8, 7.0, 6.0, 0.0, 5.0];
+/// let d = differences(&a, &b, 1024, 0.5).unwrap();
+/// assert_eq!(d.len(), 1);
+/// assert_eq!((d[0].start, d[0].end), (2048, 4096));
+/// assert_eq!((d[0].mean, d[0].max), (2.5, 3.0));
+///```
+pub fn differences(
+    a: &[f64],
+    b: &[f64],
+    chunk_size: u64,
+    threshold: f64,
+) -> Result<Vec<Difference>, Error> {
+    if threshold.is_nan() || threshold < 0.0 {
+        return Err(Error::InvalidArgument(
+            "The threshold must not be negative".to_string(),
+        ));
+    }
+    let mut found: Vec<Difference> = Vec::new();
+    // the chunks in the current run
+    let mut run = 0u64;
+    for (i, (x, y)) in a.iter().zip(b.iter()).enumerate() {
+        let delta = y - x;
+        if delta.abs() <= threshold {
+            run = 0;
+            continue;
+        }
+        let offset = i as u64 * chunk_size;
+        match found.last_mut() {
+            // a rise right after a drop is a new difference, they'd cancel out in the mean
+            Some(d) if run > 0 && delta.signum() == d.mean.signum() => {
+                run += 1;
+                d.end = offset + chunk_size;
+                d.mean += (delta - d.mean) / run as f64;
+                d.max = d.max.max(delta.abs());
+            }
+            _ => {
+                run = 1;
+                found.push(Difference {
+                    start: offset,
+                    end: offset + chunk_size,
+                    mean: delta,
+                    max: delta.abs(),
+                });
+            }
+        }
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{differences, Difference};
+
+    #[test]
+    fn runs() {
+        let a = [1.0, 1.0, 1.0, 5.0, 5.0, 5.0, 5.0, 2.0];
+        let b = [1.0, 2.0, 1.0, 4.0, 3.0, 5.0, 0.0, 2.0];
+        let d = differences(&a, &b, 10, 0.5).unwrap();
+        assert_eq!(
+            d,
+            vec![
+                Difference {
+                    start: 10,
+                    end: 20,
+                    mean: 1.0,
+                    max: 1.0
+                },
+                Difference {
+                    start: 30,
+                    end: 50,
+                    mean: -1.5,
+                    max: 2.0
+                },
+                Difference {
+                    start: 60,
+                    end: 70,
+                    mean: -5.0,
+                    max: 5.0
+                },
+            ]
+        );
+        assert_eq!(d[1].len(), 20);
+
+        // the threshold itself isn't a difference, only the common chunks are compared
+        assert!(differences(&a, &b, 10, 5.0).unwrap().is_empty());
+        assert!(differences(&a, &[], 10, 0.0).unwrap().is_empty());
+        assert!(differences(&a, &b, 10, -1.0).is_err());
+        assert!(differences(&a, &b, 10, f64::NAN).is_err());
+    }
+
+    #[test]
+    fn sign_change() {
+        let d = differences(&[4.0, 4.0, 4.0], &[7.0, 1.0, 1.0], 10, 0.5).unwrap();
+        assert_eq!(d.len(), 2);
+        assert_eq!((d[0].start, d[0].end, d[0].mean), (0, 10, 3.0));
+        assert_eq!((d[1].start, d[1].end, d[1].mean), (10, 30, -3.0));
+    }
+}
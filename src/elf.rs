@@ -0,0 +1,633 @@
+use std::convert::{Infallible, TryFrom};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::io::{Cursor, ErrorKind, Read, Seek, SeekFrom};
+
+use crate::{Class, Error, Histogram};
+
+const SHT_NULL: u32 = 0;
+const SHT_NOBITS: u32 = 8;
+const PT_LOAD: u32 = 1;
+// the real numbers are in the first section header
+const SHN_XINDEX: u16 = 0xffff;
+const PN_XNUM: u16 = 0xffff;
+/// Parts smaller than this aren't flagged as packed, their histograms say too little
+const PACKED_MIN_SIZE: u64 = 512;
+
+/// The section and program headers of an ELF file, 32 or 64-bit and of either endianness.
+///
+/// Only the headers are parsed, which is enough to tell which section and segment every byte of
+/// the file belongs to; [Elf::report] then measures the entropy of each of them.
+/// [Elf::read_from] and [Elf::report_from] do the same on a file without reading all of it into
+/// memory.
+///
+/// # Example
+///```no_run
+/// use std::fs::File;
+/// use tropy::Elf;
+///
+/// let mut file = File::open("/bin/ls").unwrap();
+/// let elf = Elf::read_from(&mut file).unwrap();
+/// for part in elf.report_from(&mut file).unwrap().sections.iter().filter(|p| p.packed) {
+///     println!("{} looks packed or encrypted", part.name);
+/// }
+///```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Elf {
+    /// Whether the file is 64-bit
+    pub is_64: bool,
+    /// Whether the file is little endian
+    pub little_endian: bool,
+    /// The target architecture, `e_machine`
+    pub machine: u16,
+    /// The sections in the order of the section header table
+    pub sections: Vec<Section>,
+    /// The program segments in the order of the program header table
+    pub segments: Vec<Segment>,
+}
+
+/// A section of an [Elf] file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    /// The name from the section name table, empty if there is none
+    pub name: String,
+    /// `sh_type`
+    pub kind: u32,
+    /// `sh_flags`
+    pub flags: u64,
+    /// Offset in the file
+    pub offset: u64,
+    /// The number of bytes; `.bss` and other sections without content take up none of the file
+    pub size: u64,
+}
+
+/// A program segment of an [Elf] file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    /// `p_type`
+    pub kind: u32,
+    /// `p_flags`, readable 4, writable 2 and executable 1
+    pub flags: u32,
+    /// Offset in the file
+    pub offset: u64,
+    /// The number of bytes in the file
+    pub file_size: u64,
+}
+
+/// The entropy of the sections and segments of an [Elf] file.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ElfReport {
+    /// 32 or 64
+    pub bits: u8,
+    /// Whether the file is little endian
+    pub little_endian: bool,
+    /// The target architecture, `e_machine`
+    pub machine: u16,
+    /// The sections with content in the file
+    pub sections: Vec<ElfPart>,
+    /// The segments with content in the file
+    pub segments: Vec<ElfPart>,
+}
+
+/// A section or segment in an [ElfReport].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ElfPart {
+    /// The name of the section, or type and flags of the segment, e.g. `LOAD r-x`
+    pub name: String,
+    /// Offset in the file
+    pub offset: u64,
+    /// The number of bytes in the file, less than in the header if the file is cut short
+    pub size: u64,
+    /// The Shannon entropy in bits per byte
+    pub entropy: f64,
+    /// The kind of data, see [Class]
+    pub class: Class,
+    /// Whether the content looks compressed or encrypted, i.e. is classified as such and large
+    /// enough to tell
+    pub packed: bool,
+}
+
+/// Reads the fields of the headers in the byte order and word size of the file
+struct Reader<'a> {
+    // the bytes of the file from `base` on
+    data: &'a [u8],
+    base: u64,
+    is_64: bool,
+    little_endian: bool,
+}
+
+impl Reader<'_> {
+    /// A reader of the same file over the bytes at `base`
+    fn over<'b>(&self, data: &'b [u8], base: u64) -> Reader<'b> {
+        Reader {
+            data,
+            base,
+            is_64: self.is_64,
+            little_endian: self.little_endian,
+        }
+    }
+
+    fn uint(&self, at: u64, len: usize) -> Result<u64, Error> {
+        let bytes = at
+            .checked_sub(self.base)
+            .and_then(|at| usize::try_from(at).ok())
+            .and_then(|at| self.data.get(at..at.checked_add(len)?))
+            .ok_or_else(truncated)?;
+        let fold = |v: u64, b: &u8| v << 8 | *b as u64;
+        Ok(if self.little_endian {
+            bytes.iter().rev().fold(0, fold)
+        } else {
+            bytes.iter().fold(0, fold)
+        })
+    }
+
+    fn u16(&self, at: u64) -> Result<u16, Error> {
+        self.uint(at, 2).map(|v| v as u16)
+    }
+
+    fn u32(&self, at: u64) -> Result<u32, Error> {
+        self.uint(at, 4).map(|v| v as u32)
+    }
+
+    /// An address, offset or size
+    fn word(&self, at: u64) -> Result<u64, Error> {
+        self.uint(at, if self.is_64 { 8 } else { 4 })
+    }
+
+    /// The offset of entry `i` of a table, which must start within the data
+    fn entry(&self, table: u64, i: u64, size: u16) -> Result<u64, Error> {
+        i.checked_mul(size as u64)
+            .and_then(|o| o.checked_add(table))
+            .filter(|at| {
+                at.checked_sub(self.base)
+                    .is_some_and(|at| at < self.data.len() as u64)
+            })
+            .ok_or_else(truncated)
+    }
+
+    /// The field at `field` of the 32 or 64-bit layout
+    fn pick(&self, field: (u64, u64)) -> u64 {
+        if self.is_64 {
+            field.1
+        } else {
+            field.0
+        }
+    }
+}
+
+fn truncated() -> Error {
+    Error::UnsupportedFormat("The ELF headers are cut short".to_string())
+}
+
+/// Read up to `len` bytes at `offset`, fewer at the end of the input
+fn fetch<R: Read + Seek>(r: &mut R, offset: u64, len: u64) -> Result<Vec<u8>, Error> {
+    let mut buf = Vec::new();
+    r.seek(SeekFrom::Start(offset))?;
+    r.by_ref().take(len).read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+impl Elf {
+    /// Parse the headers of an ELF file.
+    ///
+    /// Fails with [Error::UnsupportedFormat] if `data` isn't an ELF file or the headers are cut
+    /// short. Sections and segments may still point beyond the end of `data`.
+    pub fn parse(data: &[u8]) -> Result<Self, Error> {
+        Elf::read_from(&mut Cursor::new(data))
+    }
+
+    /// Parse the headers of an ELF file, reading only the file header, the header tables and the
+    /// section name table.
+    ///
+    /// Fails like [Elf::parse], or with [Error::Io] if reading fails.
+    pub fn read_from<R: Read + Seek>(r: &mut R) -> Result<Self, Error> {
+        let data = fetch(r, 0, 64)?;
+        if !data.starts_with(b"\x7fELF") {
+            return Err(Error::UnsupportedFormat(
+                "This is not an ELF file".to_string(),
+            ));
+        }
+        let is_64 = match data.get(4) {
+            Some(1) => false,
+            Some(2) => true,
+            _ => return Err(Error::UnsupportedFormat("Unknown ELF class".to_string())),
+        };
+        let little_endian = match data.get(5) {
+            Some(1) => true,
+            Some(2) => false,
+            _ => {
+                return Err(Error::UnsupportedFormat(
+                    "Unknown ELF byte order".to_string(),
+                ))
+            }
+        };
+        let h = Reader {
+            data: &data,
+            base: 0,
+            is_64,
+            little_endian,
+        };
+        let machine = h.u16(18)?;
+        let phoff = h.word(h.pick((28, 32)))?;
+        let shoff = h.word(h.pick((32, 40)))?;
+        let base = h.pick((42, 54));
+        let phentsize = h.u16(base)?;
+        let mut phnum = h.u16(base + 2)? as u64;
+        let shentsize = h.u16(base + 4)?;
+        let mut shnum = h.u16(base + 6)? as u64;
+        let mut shstrndx = h.u16(base + 8)? as u64;
+
+        // (offset of the name in the name table, section)
+        let mut sections = Vec::new();
+        if shoff != 0 {
+            if (shentsize as u64) < h.pick((40, 64)) {
+                return Err(Error::UnsupportedFormat(format!(
+                    "Invalid ELF section header size {}",
+                    shentsize
+                )));
+            }
+            // too many sections or segments to count in the file header
+            let data = fetch(r, shoff, shentsize as u64)?;
+            let f = h.over(&data, shoff);
+            let first = f.entry(shoff, 0, 0)?;
+            if shnum == 0 {
+                shnum = f.word(first + f.pick((20, 32)))?;
+            }
+            if shstrndx == SHN_XINDEX as u64 {
+                shstrndx = f.u32(first + f.pick((24, 40)))? as u64;
+            }
+            if phnum == PN_XNUM as u64 {
+                phnum = f.u32(first + f.pick((28, 44)))? as u64;
+            }
+            let data = fetch(r, shoff, shnum.saturating_mul(shentsize as u64))?;
+            let t = h.over(&data, shoff);
+            for i in 0..shnum {
+                let at = t.entry(shoff, i, shentsize)?;
+                let name = t.u32(at)?;
+                sections.push((
+                    name,
+                    Section {
+                        name: String::new(),
+                        kind: t.u32(at + 4)?,
+                        flags: t.word(at + 8)?,
+                        offset: t.word(at + t.pick((16, 24)))?,
+                        size: t.word(at + t.pick((20, 32)))?,
+                    },
+                ));
+            }
+        }
+        let names = match sections
+            .get(shstrndx as usize)
+            .filter(|(_, s)| s.kind != SHT_NOBITS)
+        {
+            Some((_, s)) => fetch(r, s.offset, s.size)?,
+            None => Vec::new(),
+        };
+        for (name, section) in sections.iter_mut() {
+            section.name = string(&names, *name as u64);
+        }
+
+        let mut segments = Vec::new();
+        if phoff != 0 && phnum > 0 {
+            if (phentsize as u64) < h.pick((32, 56)) {
+                return Err(Error::UnsupportedFormat(format!(
+                    "Invalid ELF program header size {}",
+                    phentsize
+                )));
+            }
+            let data = fetch(r, phoff, phnum.saturating_mul(phentsize as u64))?;
+            let p = h.over(&data, phoff);
+            for i in 0..phnum {
+                let at = p.entry(phoff, i, phentsize)?;
+                segments.push(Segment {
+                    kind: p.u32(at)?,
+                    flags: p.u32(at + p.pick((24, 4)))?,
+                    offset: p.word(at + p.pick((4, 8)))?,
+                    file_size: p.word(at + p.pick((16, 32)))?,
+                });
+            }
+        }
+
+        Ok(Elf {
+            is_64,
+            little_endian,
+            machine,
+            sections: sections.into_iter().map(|(_, s)| s).collect(),
+            segments,
+        })
+    }
+
+    /// The section with content in the file containing the given offset.
+    pub fn section_at(&self, offset: u64) -> Option<&Section> {
+        self.sections
+            .iter()
+            .filter(|s| s.in_file())
+            .find(|s| s.offset <= offset && offset - s.offset < s.size)
+    }
+
+    /// The loadable segment containing the given offset.
+    pub fn segment_at(&self, offset: u64) -> Option<&Segment> {
+        self.segments
+            .iter()
+            .filter(|s| s.kind == PT_LOAD)
+            .find(|s| s.offset <= offset && offset - s.offset < s.file_size)
+    }
+
+    /// The name of the target architecture, e.g. `x86-64`, or `unknown`.
+    pub fn machine_name(&self) -> &'static str {
+        match self.machine {
+            3 => "x86",
+            8 => "MIPS",
+            20 => "PowerPC",
+            21 => "PowerPC64",
+            40 => "ARM",
+            62 => "x86-64",
+            94 => "Xtensa",
+            183 => "AArch64",
+            243 => "RISC-V",
+            _ => "unknown",
+        }
+    }
+
+    /// Measure the entropy of every section and segment with content in the file.
+    ///
+    /// `data` is the file the headers were parsed from; parts beyond its end are cut short or left
+    /// out.
+    pub fn report(&self, data: &[u8]) -> ElfReport {
+        let measured = self.measure(|offset, size| {
+            let start = offset.min(data.len() as u64) as usize;
+            let end = offset.saturating_add(size).min(data.len() as u64) as usize;
+            Ok::<_, Infallible>(Histogram::from(&data[start..end]))
+        });
+        measured.unwrap_or_else(|e| match e {})
+    }
+
+    /// Same as [Elf::report], but reads the parts from a file one buffer at a time. The bytes of
+    /// a section are read once more for the segment it lies in.
+    ///
+    /// Fails with [Error::Io] if reading fails.
+    pub fn report_from<R: Read + Seek>(&self, r: &mut R) -> Result<ElfReport, Error> {
+        let mut buf = vec![0u8; 1 << 16];
+        self.measure(|offset, size| {
+            let mut hist = Histogram::new();
+            r.seek(SeekFrom::Start(offset))?;
+            let mut part = r.by_ref().take(size);
+            loop {
+                match part.read(&mut buf) {
+                    Ok(0) => return Ok(hist),
+                    Ok(n) => hist.update(&buf[..n])?,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        })
+    }
+
+    /// The report with the histogram of the bytes of every part, given its offset and size
+    fn measure<E>(
+        &self,
+        mut histogram: impl FnMut(u64, u64) -> Result<Histogram, E>,
+    ) -> Result<ElfReport, E> {
+        let mut part = |name: String, offset: u64, size: u64| {
+            let hist = histogram(offset, size)?;
+            let class = Class::of(&hist);
+            Ok(ElfPart {
+                name,
+                offset,
+                size: hist.total(),
+                entropy: hist.entropy(),
+                class,
+                packed: hist.total() >= PACKED_MIN_SIZE
+                    && (class == Class::Compressed || class == Class::Random),
+            })
+        };
+        let mut sections = Vec::new();
+        for s in self.sections.iter().filter(|s| s.in_file()) {
+            sections.push(part(s.name.clone(), s.offset, s.size)?);
+        }
+        let mut segments = Vec::new();
+        for s in self.segments.iter().filter(|s| s.file_size > 0) {
+            segments.push(part(s.to_string(), s.offset, s.file_size)?);
+        }
+        sections.retain(|p| p.size > 0);
+        segments.retain(|p| p.size > 0);
+        Ok(ElfReport {
+            bits: if self.is_64 { 64 } else { 32 },
+            little_endian: self.little_endian,
+            machine: self.machine,
+            sections,
+            segments,
+        })
+    }
+}
+
+/// The NUL terminated string at `at` of a string table
+fn string(table: &[u8], at: u64) -> String {
+    let name = usize::try_from(at)
+        .ok()
+        .and_then(|at| table.get(at..))
+        .map(|s| s.split(|b| *b == 0).next().unwrap_or(&[]))
+        .unwrap_or(&[]);
+    String::from_utf8_lossy(name).into_owned()
+}
+
+impl Section {
+    /// Whether the section takes up bytes of the file.
+    pub fn in_file(&self) -> bool {
+        self.kind != SHT_NULL && self.kind != SHT_NOBITS && self.size > 0
+    }
+}
+
+impl Segment {
+    /// The name of the type, e.g. `LOAD`, or the number if it is unknown.
+    pub fn kind_name(&self) -> String {
+        let name = match self.kind {
+            0 => "NULL",
+            PT_LOAD => "LOAD",
+            2 => "DYNAMIC",
+            3 => "INTERP",
+            4 => "NOTE",
+            5 => "SHLIB",
+            6 => "PHDR",
+            7 => "TLS",
+            0x6474_e550 => "GNU_EH_FRAME",
+            0x6474_e551 => "GNU_STACK",
+            0x6474_e552 => "GNU_RELRO",
+            0x6474_e553 => "GNU_PROPERTY",
+            kind => return format!("{:#x}", kind),
+        };
+        name.to_string()
+    }
+}
+
+/// The type and flags, e.g. `LOAD r-x`
+impl Display for Segment {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let flag = |bit: u32, c: char| if self.flags & bit != 0 { c } else { '-' };
+        write!(
+            f,
+            "{} {}{}{}",
+            self.kind_name(),
+            flag(4, 'r'),
+            flag(2, 'w'),
+            flag(1, 'x')
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_util::noise;
+    use crate::{Class, Elf, Error};
+    use std::io::Cursor;
+
+    /// Write `len` bytes of `value` at `at` in the byte order of the file
+    fn put(d: &mut Vec<u8>, at: usize, value: u64, len: usize, le: bool) {
+        if d.len() < at + len {
+            d.resize(at + len, 0);
+        }
+        let bytes = value.to_le_bytes();
+        for i in 0..len {
+            d[at + i] = if le { bytes[i] } else { bytes[len - 1 - i] };
+        }
+    }
+
+    /// An ELF file with code, a packed section, the name table and .bss, all in one segment
+    fn build(is_64: bool, le: bool) -> Vec<u8> {
+        let pick = |a: usize, b: usize| if is_64 { b } else { a };
+        let word = pick(4, 8);
+        let (ehsize, phentsize, shentsize) = (pick(52, 64), pick(32, 56), pick(40, 64));
+        let names = b"\0.text\0.packed\0.shstrtab\0.bss\0";
+        let (text, packed, strtab, shoff) = (0x100, 0x900, 0x1900, 0x1a00);
+
+        let mut d = vec![0u8; shoff];
+        d[..4].copy_from_slice(b"\x7fELF");
+        d[4] = pick(1, 2) as u8;
+        d[5] = if le { 1 } else { 2 };
+        d[6] = 1;
+        put(&mut d, 16, 2, 2, le);
+        put(&mut d, 18, 62, 2, le);
+        put(&mut d, 20, 1, 4, le);
+        put(&mut d, pick(28, 32), ehsize as u64, word, le);
+        put(&mut d, pick(32, 40), shoff as u64, word, le);
+        let base = pick(40, 52);
+        for (i, v) in [ehsize, phentsize, 1, shentsize, 5, 3].iter().enumerate() {
+            put(&mut d, base + 2 * i, *v as u64, 2, le);
+        }
+
+        // LOAD r-x over everything up to the name table
+        let ph = ehsize;
+        put(&mut d, ph, 1, 4, le);
+        put(&mut d, ph + pick(24, 4), 5, 4, le);
+        put(&mut d, ph + pick(4, 8), 0, word, le);
+        put(&mut d, ph + pick(16, 32), strtab as u64, word, le);
+
+        // every eighth byte is zero, like the operands of instructions
+        for (i, b) in d[text..text + 2048].iter_mut().enumerate() {
+            *b = if i % 8 == 0 { 0 } else { (i % 64) as u8 };
+        }
+        d[packed..packed + 4096].copy_from_slice(&noise(4096));
+        d[strtab..strtab + names.len()].copy_from_slice(names);
+
+        let sections = [
+            (0, 0, 0, 0, 0),
+            (1, 1, 6, text, 2048),
+            (7, 1, 2, packed, 4096),
+            (15, 3, 0, strtab, names.len()),
+            (25, 8, 3, strtab, 0x1000),
+        ];
+        for (i, (name, kind, flags, offset, size)) in sections.iter().enumerate() {
+            let at = shoff + i * shentsize;
+            put(&mut d, at, *name as u64, 4, le);
+            put(&mut d, at + 4, *kind as u64, 4, le);
+            put(&mut d, at + 8, *flags as u64, word, le);
+            put(&mut d, at + pick(16, 24), *offset as u64, word, le);
+            put(&mut d, at + pick(20, 32), *size as u64, word, le);
+        }
+        d
+    }
+
+    #[test]
+    fn layouts() {
+        let reference = Elf::parse(&build(true, true)).unwrap();
+        let names: Vec<&str> = reference.sections.iter().map(|s| &s.name[..]).collect();
+        assert_eq!(names, vec!["", ".text", ".packed", ".shstrtab", ".bss"]);
+        assert_eq!(reference.segments.len(), 1);
+        assert_eq!(reference.segments[0].to_string(), "LOAD r-x");
+        assert_eq!(reference.machine_name(), "x86-64");
+
+        for (is_64, le) in [(true, false), (false, true), (false, false)].iter() {
+            let elf = Elf::parse(&build(*is_64, *le)).unwrap();
+            assert_eq!((elf.is_64, elf.little_endian), (*is_64, *le));
+            assert_eq!(elf.sections, reference.sections);
+            assert_eq!(elf.segments, reference.segments);
+        }
+    }
+
+    #[test]
+    fn lookup() {
+        let elf = Elf::parse(&build(false, false)).unwrap();
+        let name = |offset| elf.section_at(offset).map(|s| &s.name[..]);
+        assert_eq!(name(0), None);
+        assert_eq!(name(0x100), Some(".text"));
+        assert_eq!(name(0x8ff), Some(".text"));
+        assert_eq!(name(0x900), Some(".packed"));
+        // .bss has no bytes in the file
+        assert_eq!(name(0x1900), Some(".shstrtab"));
+        assert_eq!(name(0x1980), None);
+        assert!(elf.segment_at(0x18ff).is_some());
+        assert!(elf.segment_at(0x1900).is_none());
+    }
+
+    #[test]
+    fn report() {
+        let data = build(true, false);
+        let report = Elf::parse(&data).unwrap().report(&data);
+        assert_eq!((report.bits, report.little_endian), (64, false));
+        let sections: Vec<(&str, bool)> = report
+            .sections
+            .iter()
+            .map(|p| (&p.name[..], p.packed))
+            .collect();
+        // the name table is too short to be flagged, whatever its histogram looks like
+        assert_eq!(
+            sections,
+            vec![(".text", false), (".packed", true), (".shstrtab", false)]
+        );
+        assert_eq!(report.sections[0].class, Class::Code);
+        assert_eq!(report.sections[1].class, Class::Random);
+        // 56 values with 1/64 each and zero with 1/8
+        assert!((report.sections[0].entropy - 5.625).abs() < 1e-9);
+        assert_eq!(report.segments.len(), 1);
+        assert_eq!(report.segments[0].size, 0x1900);
+
+        // cut short in the packed section
+        let short = Elf::parse(&data).unwrap().report(&data[..0xa00]);
+        assert_eq!(short.sections.len(), 2);
+        assert_eq!(short.sections[1].size, 0x100);
+        assert!(!short.sections[1].packed);
+
+        let mut file = Cursor::new(&data[..0xa00]);
+        let elf = Elf::read_from(&mut Cursor::new(&data)).unwrap();
+        assert_eq!(elf.report_from(&mut file).unwrap(), short);
+    }
+
+    #[test]
+    fn invalid() {
+        let data = build(false, true);
+        for bad in [&b"MZ\x90\x00"[..], &data[..40], &data[..0x1a10]].iter() {
+            assert!(matches!(Elf::parse(bad), Err(Error::UnsupportedFormat(_))));
+        }
+        let mut class = data.clone();
+        class[4] = 3;
+        assert!(Elf::parse(&class).is_err());
+        // no section headers at all is fine
+        let mut stripped = data;
+        stripped[32..36].copy_from_slice(&[0; 4]);
+        let elf = Elf::parse(&stripped).unwrap();
+        assert!(elf.sections.is_empty());
+        assert_eq!(elf.segments.len(), 1);
+    }
+}
@@ -0,0 +1,456 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::io::Write;
+
+use crate::{Error, Histogram};
+
+// bytes per Monte Carlo sample: 24 bits for each coordinate
+const MONTE_CARLO_BYTES: usize = 6;
+// squared radius of the circle in the 24 bit square
+const IN_CIRCLE: f64 = 16_777_215.0 * 16_777_215.0;
+
+/// Collects the statistics of the [ent](https://www.fourmilab.ch/random/) randomness test suite.
+///
+/// Like [crate::Calculator] it is fed through [Write] or [EntCalculator::update], but its state is
+/// only reset explicitly so calculators of consecutive chunks can be merged into one for the whole
+/// input.
+///
+/// # Example
+///```
+/// use tropy::EntCalculator;
+///
+/// let data: Vec<u8> = (0..=255u8).cycle().take(4096).collect();
+/// let mut c = EntCalculator::new();
+/// c.update(&data).unwrap();
+///
+/// let r = c.report();
+/// assert_eq!(r.entropy, 8.0);
+/// assert_eq!(r.chi_square, 0.0);
+/// assert_eq!(r.mean, 127.5);
+/// // counting up is about as correlated as it gets
+/// assert!(r.serial_correlation.unwrap() > 0.9);
+///```
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntCalculator {
+    hist: Histogram,
+    sum: u64,
+    // serial correlation: first and last byte, sum of products of neighbours and sum of squares
+    first: u8,
+    last: u8,
+    products: f64,
+    squares: f64,
+    // Monte Carlo: samples inside the circle, all samples and the bytes of an incomplete sample
+    inside: u64,
+    samples: u64,
+    pending: [u8; MONTE_CARLO_BYTES],
+    npending: usize,
+}
+
+/// The results of an [EntCalculator].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EntReport {
+    /// The number of bytes evaluated
+    pub bytes: u64,
+    /// Shannon entropy in bits per byte
+    pub entropy: f64,
+    /// Chi-square statistic of the byte counts against a uniform distribution
+    pub chi_square: f64,
+    /// The probability of a chi-square value at least this large for truly random bytes
+    pub p_value: f64,
+    /// Arithmetic mean of the bytes, 127.5 for random data
+    pub mean: f64,
+    /// Monte Carlo estimate of π, `None` if there were fewer than 6 bytes
+    pub monte_carlo_pi: Option<f64>,
+    /// Serial correlation coefficient of neighbouring bytes, `None` if undefined (e.g. all bytes
+    /// are the same)
+    pub serial_correlation: Option<f64>,
+}
+
+impl EntCalculator {
+    /// Instantiate a new calculator.
+    pub fn new() -> Self {
+        EntCalculator {
+            hist: Histogram::new(),
+            sum: 0,
+            first: 0,
+            last: 0,
+            products: 0.0,
+            squares: 0.0,
+            inside: 0,
+            samples: 0,
+            pending: [0u8; MONTE_CARLO_BYTES],
+            npending: 0,
+        }
+    }
+
+    /// Evaluate the given bytes.
+    ///
+    /// Fails with [Error::Overflow] without evaluating anything if the number of bytes or their
+    /// sum would exceed a u64.
+    pub fn update(&mut self, input: &[u8]) -> Result<(), Error> {
+        if input.is_empty() {
+            return Ok(());
+        }
+        // the sum of a slice fits into a u64, only the running one may not
+        let sum = input.iter().map(|b| *b as u64).sum::<u64>();
+        self.sum = self.sum.checked_add(sum).ok_or(Error::Overflow)?;
+        if let Err(e) = self.hist.update(input) {
+            self.sum -= sum;
+            return Err(e);
+        }
+        if self.hist.total() == input.len() as u64 {
+            self.first = input[0];
+        } else {
+            self.products += self.last as f64 * input[0] as f64;
+        }
+
+        input
+            .iter()
+            .for_each(|b| self.squares += (*b as f64) * (*b as f64));
+        input
+            .windows(2)
+            .for_each(|w| self.products += w[0] as f64 * w[1] as f64);
+        self.last = input[input.len() - 1];
+
+        for b in input {
+            self.pending[self.npending] = *b;
+            self.npending += 1;
+            if self.npending == MONTE_CARLO_BYTES {
+                self.sample();
+            }
+        }
+        Ok(())
+    }
+
+    /// Append the state of a calculator which has been fed the bytes directly following the ones
+    /// seen by this one.
+    ///
+    /// Monte Carlo samples are formed separately on both sides, so the estimate of π differs
+    /// slightly from feeding all bytes to one calculator unless the bytes seen by this calculator
+    /// are a multiple of 6.
+    ///
+    /// Fails with [Error::Overflow] without changing anything if the number of bytes or their sum
+    /// would exceed a u64.
+    pub fn merge(&mut self, next: &EntCalculator) -> Result<(), Error> {
+        if next.hist.is_empty() {
+            return Ok(());
+        }
+        let sum = self.sum.checked_add(next.sum).ok_or(Error::Overflow)?;
+        self.hist.merge(&next.hist)?;
+        self.sum = sum;
+        if self.hist.total() == next.hist.total() {
+            self.first = next.first;
+        } else {
+            self.products += self.last as f64 * next.first as f64;
+        }
+        self.last = next.last;
+        self.products += next.products;
+        self.squares += next.squares;
+        self.inside += next.inside;
+        self.samples += next.samples;
+        self.pending = next.pending;
+        self.npending = next.npending;
+        Ok(())
+    }
+
+    /// The histogram of the bytes evaluated so far.
+    pub fn histogram(&self) -> &Histogram {
+        &self.hist
+    }
+
+    /// Calculate the statistics over the bytes evaluated so far.
+    pub fn report(&self) -> EntReport {
+        let n = self.hist.total() as f64;
+        let chi_square = self.hist.chi_square();
+
+        let serial_correlation = if self.hist.is_empty() {
+            None
+        } else {
+            // like ent, the last byte is correlated with the first one
+            let products = self.products + self.last as f64 * self.first as f64;
+            let sum = self.sum as f64;
+            let denominator = n * self.squares - sum * sum;
+            if denominator == 0.0 {
+                None
+            } else {
+                Some((n * products - sum * sum) / denominator)
+            }
+        };
+
+        EntReport {
+            bytes: self.hist.total(),
+            entropy: self.hist.entropy(),
+            chi_square,
+            p_value: if self.hist.is_empty() {
+                1.0
+            } else {
+                chi_square_p_value(chi_square, 255.0)
+            },
+            mean: if self.hist.is_empty() {
+                0.0
+            } else {
+                self.sum as f64 / n
+            },
+            monte_carlo_pi: if self.samples == 0 {
+                None
+            } else {
+                Some(4.0 * self.inside as f64 / self.samples as f64)
+            },
+            serial_correlation,
+        }
+    }
+
+    /// Drop all state.
+    pub fn clear(&mut self) {
+        *self = EntCalculator::new();
+    }
+
+    fn sample(&mut self) {
+        let p = &self.pending;
+        let x = u32::from_be_bytes([0, p[0], p[1], p[2]]) as f64;
+        let y = u32::from_be_bytes([0, p[3], p[4], p[5]]) as f64;
+        if x * x + y * y <= IN_CIRCLE {
+            self.inside += 1;
+        }
+        self.samples += 1;
+        self.npending = 0;
+    }
+}
+
+impl Default for EntCalculator {
+    fn default() -> Self {
+        EntCalculator::new()
+    }
+}
+
+impl Write for EntCalculator {
+    fn write(&mut self, input: &[u8]) -> io::Result<usize> {
+        self.update(input)?;
+        Ok(input.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl EntReport {
+    /// Calculate the statistics over the given bytes.
+    pub fn of(input: &[u8]) -> Self {
+        let mut c = EntCalculator::new();
+        // a slice can neither hold more than u64::MAX bytes nor sum up to more than that
+        c.update(input)
+            .expect("The statistics of a slice cannot overflow");
+        c.report()
+    }
+}
+
+/// Formats the report the way ent does.
+impl Display for EntReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "Entropy = {:.6} bits per byte.", self.entropy)?;
+        writeln!(f)?;
+        writeln!(f, "Optimum compression would reduce the size")?;
+        writeln!(
+            f,
+            "of this {} byte file by {:.0} percent.",
+            self.bytes,
+            (100.0 * (8.0 - self.entropy) / 8.0).max(0.0)
+        )?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "Chi square distribution for {} samples is {:.2}, and randomly",
+            self.bytes, self.chi_square
+        )?;
+        writeln!(
+            f,
+            "would exceed this value {:.2} percent of the times.",
+            self.p_value * 100.0
+        )?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "Arithmetic mean value of data bytes is {:.4} (127.5 = random).",
+            self.mean
+        )?;
+        match self.monte_carlo_pi {
+            Some(pi) => writeln!(
+                f,
+                "Monte Carlo value for Pi is {:.9} (error {:.2} percent).",
+                pi,
+                100.0 * (std::f64::consts::PI - pi).abs() / std::f64::consts::PI
+            )?,
+            None => writeln!(f, "Monte Carlo value for Pi is undefined.")?,
+        }
+        match self.serial_correlation {
+            Some(scc) => write!(
+                f,
+                "Serial correlation coefficient is {:.6} (totally uncorrelated = 0.0).",
+                scc
+            ),
+            None => write!(
+                f,
+                "Serial correlation coefficient is undefined (all values equal!)."
+            ),
+        }
+    }
+}
+
+/// The upper tail probability of the chi-square distribution with `dof` degrees of freedom.
+pub(crate) fn chi_square_p_value(chi_square: f64, dof: f64) -> f64 {
+    gamma_q(dof / 2.0, chi_square / 2.0)
+}
+
+/// The regularised upper incomplete gamma function Q(a, x), following Numerical Recipes.
+fn gamma_q(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 1.0;
+    }
+    if x < a + 1.0 {
+        // series representation of P(a, x)
+        let mut sum = 1.0 / a;
+        let mut term = sum;
+        let mut n = a;
+        for _ in 0..1000 {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+            if term.abs() < sum.abs() * 1e-15 {
+                break;
+            }
+        }
+        1.0 - sum * (-x + a * x.ln() - ln_gamma(a)).exp()
+    } else {
+        // continued fraction representation of Q(a, x) using Lentz's method
+        let tiny = 1e-300;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..1000 {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < tiny {
+                d = tiny;
+            }
+            c = b + an / c;
+            if c.abs() < tiny {
+                c = tiny;
+            }
+            d = 1.0 / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < 1e-15 {
+                break;
+            }
+        }
+        (-x + a * x.ln() - ln_gamma(a)).exp() * h
+    }
+}
+
+/// ln(Γ(x)) for x > 0 using the Lanczos approximation.
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.180_091_729_471_46,
+        -86.505_320_329_416_77,
+        24.014_098_240_830_91,
+        -1.231_739_572_450_155,
+        0.120_865_097_386_617_9e-2,
+        -0.539_523_938_495_3e-5,
+    ];
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let mut y = x;
+    let series = COEFFICIENTS.iter().fold(1.000_000_000_190_015, |s, c| {
+        y += 1.0;
+        s + c / y
+    });
+    -tmp + (2.506_628_274_631_000_5 * series / x).ln()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{chi_square_p_value, ln_gamma};
+    use crate::test_util::noise;
+    use crate::{EntCalculator, EntReport, Error};
+
+    #[test]
+    fn special_functions() {
+        // Γ(5) = 24
+        assert!((ln_gamma(5.0) - 24f64.ln()).abs() < 1e-9);
+        // the median of chi-square with 2 dof is 2 ln 2
+        assert!((chi_square_p_value(2.0 * 2f64.ln(), 2.0) - 0.5).abs() < 1e-9);
+        // reference values for 255 dof
+        assert!((chi_square_p_value(255.0, 255.0) - 0.4881).abs() < 1e-3);
+        assert!((chi_square_p_value(293.2478, 255.0) - 0.05).abs() < 1e-3);
+        assert!(chi_square_p_value(1000.0, 255.0) < 1e-6);
+    }
+
+    #[test]
+    fn random() {
+        let r = EntReport::of(&noise(1 << 20));
+        assert!(r.entropy > 7.999);
+        assert!(r.p_value > 0.01 && r.p_value < 0.99);
+        assert!((r.mean - 127.5).abs() < 0.5);
+        assert!((r.monte_carlo_pi.unwrap() - std::f64::consts::PI).abs() < 0.02);
+        assert!(r.serial_correlation.unwrap().abs() < 0.01);
+    }
+
+    #[test]
+    fn constant() {
+        let r = EntReport::of(&[0x41u8; 600]);
+        assert_eq!(r.entropy, 0.0);
+        assert_eq!(r.mean, 65.0);
+        assert!(r.p_value < 1e-6);
+        assert_eq!(r.monte_carlo_pi, Some(4.0));
+        assert_eq!(r.serial_correlation, None);
+
+        let r = EntReport::of(&[]);
+        assert_eq!(r.bytes, 0);
+        assert_eq!(r.monte_carlo_pi, None);
+    }
+
+    #[test]
+    fn merge() {
+        let data = noise(6000);
+        let whole = EntReport::of(&data);
+
+        // chunk sizes divisible by 6 keep the Monte Carlo samples intact
+        let mut merged = EntCalculator::new();
+        for chunk in data.chunks(600) {
+            let mut c = EntCalculator::new();
+            c.update(chunk).unwrap();
+            merged.merge(&c).unwrap();
+        }
+        let merged = merged.report();
+
+        assert_eq!(merged.bytes, whole.bytes);
+        assert_eq!(merged.chi_square, whole.chi_square);
+        assert_eq!(merged.mean, whole.mean);
+        assert_eq!(merged.monte_carlo_pi, whole.monte_carlo_pi);
+        let (a, b) = (merged.serial_correlation, whole.serial_correlation);
+        assert!((a.unwrap() - b.unwrap()).abs() < 1e-12);
+
+        // feeding in pieces is the same as feeding at once
+        let mut c = EntCalculator::new();
+        data.chunks(7).for_each(|chunk| c.update(chunk).unwrap());
+        assert_eq!(c.report(), whole);
+    }
+
+    #[test]
+    fn overflow() {
+        let mut c = EntCalculator::new();
+        c.update(&[1, 2, 3]).unwrap();
+        c.sum = u64::MAX;
+        let before = c.clone();
+        assert!(matches!(c.update(&[1]), Err(Error::Overflow)));
+        assert!(matches!(c.merge(&before), Err(Error::Overflow)));
+        assert_eq!(c, before);
+        c.update(&[0]).unwrap();
+        assert_eq!(c.report().bytes, 4);
+    }
+}
@@ -0,0 +1,72 @@
+use core::fmt;
+use core::fmt::{Display, Formatter};
+#[cfg(feature = "std")]
+use std::io;
+
+/// The errors of the library.
+///
+/// Without the `std` feature only the variants which don't need an allocator are available. As
+/// cargo unifies features, another crate may turn `std` on and add variants, so the enum is
+/// non-exhaustive and matches need a wildcard arm.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// A counter would have exceeded the range of its integer type.
+    /// The counts are left as they were before the failed update.
+    Overflow,
+    /// A length which must hold at least one byte (or thread) was zero; names the argument
+    ZeroLength(&'static str),
+    /// An argument is outside of its valid range
+    #[cfg(feature = "std")]
+    InvalidArgument(String),
+    /// A string or file is not in the expected format
+    #[cfg(feature = "std")]
+    UnsupportedFormat(String),
+    /// Reading the input failed
+    #[cfg(feature = "std")]
+    Io(io::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Error::Overflow => write!(f, "A counter exceeded the range of its integer type"),
+            Error::ZeroLength(what) => write!(f, "The {} must be at least 1", what),
+            #[cfg(feature = "std")]
+            Error::InvalidArgument(msg) => write!(f, "{}", msg),
+            #[cfg(feature = "std")]
+            Error::UnsupportedFormat(msg) => write!(f, "{}", msg),
+            #[cfg(feature = "std")]
+            Error::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+/// Errors of a [std::io::Write] implementation are reported as [io::Error]s wrapping the
+/// [Error], which can be recovered with [io::Error::into_inner].
+#[cfg(feature = "std")]
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io(e) => e,
+            e => io::Error::other(e),
+        }
+    }
+}
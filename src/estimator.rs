@@ -0,0 +1,204 @@
+use std::f64::consts::LN_2;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::{Error, Histogram};
+
+/// How the Shannon entropy of a chunk is estimated from its byte counts.
+///
+/// The plug-in estimate, i.e. the entropy of the observed frequencies, is biased low for small
+/// samples: `n` bytes can show at most `n` distinct values, so it never exceeds `log2(n)` and
+/// random 64 byte chunks come out at about 5.8 bits. The corrected estimators add back part of
+/// the missing entropy, from a third for Miller-Madow to nearly all of it for Chao-Shen on random
+/// data; [Estimator::Normalised] instead rescales to the maximum reachable.
+///
+/// All of them are in bits per byte, clamped to 0 to 8.
+///
+/// # Example
+///```
+/// use tropy::{Estimator, Histogram};
+///
+/// let all: Vec<u8> = (0..=255u8).collect();
+/// let h = Histogram::from(&all[..64]);
+/// assert_eq!(Estimator::PlugIn.of(&h), 6.0);
+/// assert_eq!(Estimator::Normalised.of(&h), 8.0);
+/// assert!(Estimator::MillerMadow.of(&h) > 6.5);
+///```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Estimator {
+    /// The entropy of the observed frequencies, see [Histogram::entropy]
+    #[default]
+    PlugIn,
+    /// Plug-in plus the first order bias term `(m - 1) / 2n` nats, `m` being the number of
+    /// distinct bytes seen (Miller 1955)
+    MillerMadow,
+    /// Coverage-adjusted Horvitz-Thompson estimator, which accounts for byte values not seen at
+    /// all by the number seen exactly once (Chao and Shen 2003)
+    ChaoShen,
+    /// Jackknife over the plug-in estimates leaving out one byte at a time (Zahl 1977)
+    Jackknife,
+    /// The plug-in estimate scaled by 8 bits over the maximum `n` bytes can reach, see
+    /// [Histogram::normalised_entropy]
+    Normalised,
+}
+
+impl Estimator {
+    /// All estimators, in the order of the help text.
+    pub const ALL: [Estimator; 5] = [
+        Estimator::PlugIn,
+        Estimator::MillerMadow,
+        Estimator::ChaoShen,
+        Estimator::Jackknife,
+        Estimator::Normalised,
+    ];
+
+    /// Estimate the entropy of the source of the bytes counted by a histogram in bits per byte.
+    ///
+    /// An empty histogram has an entropy of 0 under every estimator.
+    pub fn of(&self, h: &Histogram) -> f64 {
+        if h.is_empty() {
+            return 0.0;
+        }
+        let e = match self {
+            Estimator::PlugIn => h.entropy(),
+            Estimator::MillerMadow => miller_madow(h),
+            Estimator::ChaoShen => chao_shen(h),
+            Estimator::Jackknife => jackknife(h),
+            Estimator::Normalised => 8.0 * h.normalised_entropy(),
+        };
+        e.clamp(0.0, 8.0)
+    }
+}
+
+fn miller_madow(h: &Histogram) -> f64 {
+    let seen = h.counts().iter().filter(|c| **c > 0).count() as f64;
+    h.entropy() + (seen - 1.0) / (2.0 * h.total() as f64 * LN_2)
+}
+
+fn chao_shen(h: &Histogram) -> f64 {
+    let n = h.total() as f64;
+    let singletons = h.counts().iter().filter(|c| **c == 1).count() as f64;
+    // with nothing but singletons the coverage would be 0
+    let coverage = 1.0 - singletons.min(n - 1.0) / n;
+    h.counts()
+        .iter()
+        .filter(|c| **c > 0)
+        .map(|c| coverage * *c as f64 / n)
+        // divided by the probability of the value showing up in the sample at all
+        .map(|p| -p * p.log2() / (1.0 - (1.0 - p).powf(n)))
+        .sum()
+}
+
+fn jackknife(h: &Histogram) -> f64 {
+    let n = h.total() as f64;
+    if h.total() < 2 {
+        return h.entropy();
+    }
+    // H = log2(n) - sum(c * log2(c)) / n, so leaving out one byte only changes one term
+    let xlog2x = |c: f64| if c > 0.0 { c * c.log2() } else { 0.0 };
+    let sum: f64 = h.counts().iter().map(|c| xlog2x(*c as f64)).sum();
+    let m = n - 1.0;
+    // every byte counted c times gives the same estimate when left out
+    let left_out: f64 = h
+        .counts()
+        .iter()
+        .filter(|c| **c > 0)
+        .map(|c| {
+            let c = *c as f64;
+            c * (m.log2() - (sum - xlog2x(c) + xlog2x(c - 1.0)) / m)
+        })
+        .sum();
+    n * h.entropy() - m * left_out / n
+}
+
+impl Display for Estimator {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name = match self {
+            Estimator::PlugIn => "plugin",
+            Estimator::MillerMadow => "miller-madow",
+            Estimator::ChaoShen => "chao-shen",
+            Estimator::Jackknife => "jackknife",
+            Estimator::Normalised => "normalised",
+        };
+        f.pad(name)
+    }
+}
+
+impl FromStr for Estimator {
+    type Err = Error;
+
+    /// Parses the names written by [Display].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Estimator::ALL
+            .iter()
+            .find(|e| e.to_string() == s)
+            .cloned()
+            .ok_or_else(|| Error::UnsupportedFormat(format!("Unknown estimator {:?}", s)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_util::noise;
+    use crate::{Estimator, Histogram};
+
+    #[test]
+    fn small_samples() {
+        // averaged over many chunks of random bytes, the share of the gap to 8 bits each correction
+        // closes at least, they are known to differ a lot when most values are unseen
+        let closes = [
+            (Estimator::MillerMadow, 0.25),
+            (Estimator::Jackknife, 0.5),
+            (Estimator::ChaoShen, 0.9),
+        ];
+        for &n in &[64usize, 128, 256] {
+            let data = noise(n * 200);
+            let mean = |e: Estimator| {
+                data.chunks(n)
+                    .map(|c| e.of(&Histogram::from(c)))
+                    .sum::<f64>()
+                    / 200.0
+            };
+            let plugin = mean(Estimator::PlugIn);
+            assert!(plugin < (n as f64).log2().min(8.0));
+            for &(e, share) in closes.iter() {
+                let corrected = mean(e);
+                assert!(corrected - plugin > share * (8.0 - plugin), "{} {}", e, n);
+            }
+            assert!(mean(Estimator::Normalised) > 7.0);
+        }
+    }
+
+    #[test]
+    fn exact_cases() {
+        let constant = Histogram::from(&[9u8; 100][..]);
+        let empty = Histogram::new();
+        for e in Estimator::ALL.iter() {
+            assert_eq!(e.of(&constant), 0.0, "{}", e);
+            assert_eq!(e.of(&empty), 0.0, "{}", e);
+        }
+
+        // two values seen 50 times each: one bit, plus 1 / (200 ln 2) for Miller-Madow
+        let coin: Vec<u8> = (0..100).map(|i| i % 2).collect();
+        let h = Histogram::from(&coin[..]);
+        let mm = 1.0 + 1.0 / (200.0 * std::f64::consts::LN_2);
+        assert!((Estimator::MillerMadow.of(&h) - mm).abs() < 1e-12);
+        // leaving out one byte makes the split 49:50
+        let p = 49.0f64 / 99.0;
+        let loo = -(p * p.log2() + (1.0 - p) * (1.0 - p).log2());
+        let jk = 100.0 - 99.0 * loo;
+        assert!((Estimator::Jackknife.of(&h) - jk).abs() < 1e-9);
+        // no singletons, so Chao-Shen only corrects for unseen values
+        assert!(Estimator::ChaoShen.of(&h) >= 1.0);
+        assert_eq!(Estimator::Normalised.of(&h), 8.0 / 100f64.log2().min(8.0));
+    }
+
+    #[test]
+    fn names() {
+        for e in Estimator::ALL.iter() {
+            assert_eq!(&e.to_string().parse::<Estimator>().unwrap(), e);
+        }
+        assert!("grassberger".parse::<Estimator>().is_err());
+    }
+}
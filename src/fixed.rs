@@ -0,0 +1,153 @@
+//! Entropy in fixed point arithmetic for targets without a floating point unit.
+
+use crate::{Error, StackCalculator};
+
+/// The value of one bit per byte in the results of [fixed_entropy], i.e. they are in Q8.8
+/// format and range from 0 to `8 * FIXED_ONE`.
+pub const FIXED_ONE: u16 = 256;
+
+// log2(1 + i/256) in Q16, the last entry being log2(2)
+const LOG2_TABLE: [u32; 257] = [
+    0, 369, 736, 1102, 1466, 1829, 2190, 2551, 2909, 3267, 3623, 3978, 4331, 4683, 5034, 5384,
+    5732, 6079, 6425, 6769, 7112, 7454, 7795, 8134, 8473, 8810, 9146, 9480, 9814, 10146, 10477,
+    10807, 11136, 11464, 11791, 12116, 12440, 12764, 13086, 13407, 13727, 14046, 14363, 14680,
+    14996, 15310, 15624, 15937, 16248, 16559, 16868, 17177, 17484, 17791, 18096, 18401, 18704,
+    19007, 19308, 19609, 19909, 20207, 20505, 20802, 21098, 21393, 21687, 21980, 22272, 22564,
+    22854, 23144, 23433, 23720, 24007, 24293, 24579, 24863, 25146, 25429, 25711, 25992, 26272,
+    26551, 26830, 27108, 27384, 27660, 27936, 28210, 28484, 28757, 29029, 29300, 29571, 29840,
+    30109, 30378, 30645, 30912, 31178, 31443, 31707, 31971, 32234, 32496, 32758, 33019, 33279,
+    33538, 33797, 34055, 34312, 34569, 34825, 35080, 35334, 35588, 35841, 36094, 36346, 36597,
+    36847, 37097, 37346, 37595, 37842, 38090, 38336, 38582, 38827, 39072, 39316, 39559, 39802,
+    40044, 40286, 40527, 40767, 41006, 41246, 41484, 41722, 41959, 42196, 42432, 42667, 42902,
+    43137, 43370, 43603, 43836, 44068, 44300, 44530, 44761, 44990, 45220, 45448, 45676, 45904,
+    46131, 46357, 46583, 46809, 47034, 47258, 47482, 47705, 47928, 48150, 48372, 48593, 48813,
+    49034, 49253, 49472, 49691, 49909, 50127, 50344, 50560, 50776, 50992, 51207, 51422, 51636,
+    51850, 52063, 52276, 52488, 52700, 52911, 53122, 53332, 53542, 53751, 53960, 54169, 54377,
+    54584, 54791, 54998, 55204, 55410, 55615, 55820, 56025, 56229, 56432, 56635, 56838, 57040,
+    57242, 57443, 57644, 57845, 58045, 58245, 58444, 58643, 58841, 59039, 59237, 59434, 59631,
+    59827, 60023, 60219, 60414, 60609, 60803, 60997, 61190, 61384, 61576, 61769, 61961, 62152,
+    62343, 62534, 62725, 62915, 63104, 63294, 63483, 63671, 63859, 64047, 64234, 64421, 64608,
+    64794, 64980, 65166, 65351, 65536,
+];
+
+/// Calculate the Shannon entropy of the given byte counts in bits per byte as Q8.8 fixed point
+/// number, i.e. multiplied by [FIXED_ONE], using integer arithmetic only.
+///
+/// Logarithms come from a table of 257 entries with linear interpolation, which is accurate to
+/// about 2e-5 bits. After rounding to Q8.8 the result is at most one unit (1/256 bit) away from
+/// the floating point entropy, for any number of counted bytes.
+///
+/// # Example
+///```
+/// use tropy::{fixed_entropy, FIXED_ONE};
+///
+/// let mut counts = [0u32; 256];
+/// counts[0] = 3;
+/// counts[1] = 1;
+/// // 0.811278 bits
+/// assert_eq!(fixed_entropy(&counts), 208);
+/// assert_eq!(fixed_entropy(&[1u32; 256]), 8 * FIXED_ONE);
+///```
+pub fn fixed_entropy(counts: &[u32; 256]) -> u16 {
+    let total: u64 = counts.iter().map(|c| *c as u64).sum();
+    if total == 0 {
+        return 0;
+    }
+    // H = log2(n) - sum(c * log2(c)) / n, all in Q16
+    // the sum stays below 2^61: c < 2^32 and log2(c) < 2^21 in Q16
+    let sum: u64 = counts
+        .iter()
+        .filter(|c| **c > 0)
+        .map(|c| *c as u64 * log2_q16(*c as u64))
+        .sum();
+    let h = log2_q16(total).saturating_sub((sum + total / 2) / total);
+    // round to Q8.8
+    ((h + 128) >> 8).min(8 * FIXED_ONE as u64) as u16
+}
+
+/// Convenience method: Counts the given input on the stack and returns its entropy as in
+/// [fixed_entropy].
+///
+/// Fails with [Error::Overflow] if a byte value occurs more than `u32::MAX` times.
+pub fn slice_entropy_fixed(input: &[u8]) -> Result<u16, Error> {
+    let mut c = StackCalculator::new();
+    c.update(input)?;
+    Ok(c.entropy_fixed())
+}
+
+/// log2(x) in Q16 for x >= 1
+fn log2_q16(x: u64) -> u64 {
+    let exponent = 63 - x.leading_zeros() as u64;
+    // normalise so the leading one is bit 63, the next 8 bits index the table and the 8 after
+    // them interpolate between two entries
+    let mantissa = x << (63 - exponent);
+    let index = ((mantissa >> 55) & 0xff) as usize;
+    let fraction = (mantissa >> 47) & 0xff;
+    let lo = LOG2_TABLE[index] as u64;
+    let hi = LOG2_TABLE[index + 1] as u64;
+    (exponent << 16) + lo + (((hi - lo) * fraction + 128) >> 8)
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::log2_q16;
+    use crate::test_util::{xorshift, SEED};
+    use crate::{fixed_entropy, slice_entropy, slice_entropy_fixed, FIXED_ONE};
+
+    #[test]
+    fn log2() {
+        for x in (1u64..100_000).chain((0..40).map(|s| (1u64 << s) + 12345)) {
+            let expected = (x as f64).log2() * 65536.0;
+            assert!((log2_q16(x) as f64 - expected).abs() < 2.0, "{}", x);
+        }
+    }
+
+    #[test]
+    fn error_bound() {
+        let mut random = xorshift(SEED);
+        let mut next = move || random.next().unwrap();
+
+        for &size in &[
+            1usize,
+            2,
+            3,
+            7,
+            64,
+            100,
+            255,
+            256,
+            1000,
+            1024,
+            4096,
+            65536,
+            1 << 20,
+        ] {
+            // uniform, skewed and sparse data
+            let inputs: Vec<Vec<u8>> = vec![
+                (0..size).map(|_| next() as u8).collect(),
+                (0..size).map(|_| (next() as u8) & (next() as u8)).collect(),
+                (0..size).map(|_| (next() % 5) as u8 * 50).collect(),
+            ];
+            for input in inputs {
+                let fixed = slice_entropy_fixed(&input).unwrap() as f64 / FIXED_ONE as f64;
+                let float = slice_entropy(&input);
+                assert!(
+                    (fixed - float).abs() <= 1.0 / 256.0,
+                    "{} bytes: {} vs {}",
+                    size,
+                    fixed,
+                    float
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn extremes() {
+        assert_eq!(fixed_entropy(&[0u32; 256]), 0);
+        let mut counts = [0u32; 256];
+        counts[7] = u32::MAX;
+        assert_eq!(fixed_entropy(&counts), 0);
+        assert_eq!(fixed_entropy(&[u32::MAX; 256]), 8 * FIXED_ONE);
+    }
+}
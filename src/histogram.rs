@@ -0,0 +1,463 @@
+use std::convert::From;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::metric;
+use crate::Error;
+
+/// Occurrence counts of each byte value.
+///
+/// Unlike [crate::Calculator] a histogram is never reset implicitly, so it can be inspected,
+/// cloned and combined with histograms of other inputs before the entropy is calculated.
+///
+/// # Example
+///```
+/// use tropy::Histogram;
+///
+/// let mut a = Histogram::from(&[0u8, 0u8][..]);
+/// let b = Histogram::from(&[1u8, 1u8][..]);
+/// assert_eq!(a.entropy(), 0.0);
+///
+/// // the entropy of the concatenation, without looking at the data again
+/// a.merge(&b).unwrap();
+/// assert_eq!(a.entropy(), 1.0);
+///
+/// a.subtract(&b).unwrap();
+/// assert_eq!(a, Histogram::from(&[0u8, 0u8][..]));
+///```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    // boxed for the same reason as in Calculator
+    counts: Box<[u64; 256]>,
+    total: u64,
+}
+
+impl Histogram {
+    /// Instantiate an empty histogram.
+    pub fn new() -> Self {
+        Histogram {
+            counts: Box::new([0u64; 256]),
+            total: 0,
+        }
+    }
+
+    pub(crate) fn from_counts(counts: &[u64; 256]) -> Self {
+        Histogram {
+            counts: Box::new(*counts),
+            total: counts.iter().sum(),
+        }
+    }
+
+    /// Count the given bytes.
+    ///
+    /// Fails with [Error::Overflow] without counting anything if the total would exceed a u64.
+    pub fn update(&mut self, input: &[u8]) -> Result<(), Error> {
+        // no count can overflow if the total doesn't
+        self.total = self
+            .total
+            .checked_add(input.len() as u64)
+            .ok_or(Error::Overflow)?;
+        count(&mut self.counts, input);
+        Ok(())
+    }
+
+    /// How often the given byte has been counted.
+    pub fn count(&self, byte: u8) -> u64 {
+        self.counts[byte as usize]
+    }
+
+    /// The counts of all byte values, indexed by the byte.
+    pub fn counts(&self) -> &[u64; 256] {
+        &self.counts
+    }
+
+    /// The number of bytes counted.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Whether no bytes have been counted.
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    /// Add the counts of another histogram to this one.
+    ///
+    /// Fails with [Error::Overflow] without changing anything if the total would exceed a u64.
+    pub fn merge(&mut self, other: &Histogram) -> Result<(), Error> {
+        self.total = self.total.checked_add(other.total).ok_or(Error::Overflow)?;
+        self.counts
+            .iter_mut()
+            .zip(other.counts.iter())
+            .for_each(|(c, o)| *c += *o);
+        Ok(())
+    }
+
+    /// Remove the counts of another histogram from this one, e.g. to drop a chunk which has been
+    /// merged before.
+    ///
+    /// Fails with [Error::InvalidArgument] without changing anything if `other` has counted a
+    /// byte more often than this histogram.
+    pub fn subtract(&mut self, other: &Histogram) -> Result<(), Error> {
+        if self
+            .counts
+            .iter()
+            .zip(other.counts.iter())
+            .any(|(c, o)| o > c)
+        {
+            return Err(Error::InvalidArgument(
+                "Subtracted a histogram which is not part of this one".to_string(),
+            ));
+        }
+        self.counts
+            .iter_mut()
+            .zip(other.counts.iter())
+            .for_each(|(c, o)| *c -= *o);
+        self.total -= other.total;
+        Ok(())
+    }
+
+    /// Reset all counts to zero.
+    pub fn clear(&mut self) {
+        self.counts.iter_mut().for_each(|c| *c = 0u64);
+        self.total = 0;
+    }
+
+    /// Calculate Shannon entropy over the counted bytes.
+    pub fn entropy(&self) -> f64 {
+        metric::shannon(self.counts.iter().cloned(), self.total)
+    }
+
+    /// Calculate the Shannon entropy relative to the most `n` bytes can show, i.e.
+    /// `H / min(8, log2(n))`, between 0 and 1.
+    ///
+    /// `n` bytes hold at most `n` distinct values, so the entropy of a short chunk can't reach 8
+    /// bits even for random data; this makes chunks of any size comparable. It is 0 for fewer
+    /// than two bytes.
+    pub fn normalised_entropy(&self) -> f64 {
+        if self.total < 2 {
+            return 0.0;
+        }
+        let max = (self.total as f64).log2().min(8.0);
+        (self.entropy() / max).min(1.0)
+    }
+
+    /// Calculate the min-entropy H∞ = -log2(max p) over the counted bytes.
+    ///
+    /// This is the most conservative of the Rényi entropies and the one relevant for how well
+    /// the most likely byte can be guessed.
+    pub fn min_entropy(&self) -> f64 {
+        metric::min(self.counts.iter().cloned(), self.total)
+    }
+
+    /// Calculate the collision entropy H₂ = -log2(sum p²) over the counted bytes, i.e. the
+    /// Rényi entropy of order 2.
+    pub fn collision_entropy(&self) -> f64 {
+        self.renyi_entropy(2.0)
+    }
+
+    /// Calculate the Rényi entropy of order `alpha` over the counted bytes.
+    ///
+    /// An order of 0 gives the Hartley entropy (log2 of the number of distinct bytes), 1 the
+    /// Shannon entropy, 2 the collision entropy and infinity the min-entropy.
+    ///
+    /// Returns NaN if `alpha` is negative or NaN.
+    pub fn renyi_entropy(&self, alpha: f64) -> f64 {
+        metric::renyi(self.counts.iter().cloned(), self.total, alpha)
+    }
+
+    /// Calculate Pearson's chi-square statistic of the counts against a uniform distribution of
+    /// the byte values, as reported by ent.
+    ///
+    /// For random bytes it follows the chi-square distribution with 255 degrees of freedom, see
+    /// [crate::EntReport::p_value]. It is 0 if no bytes have been counted.
+    pub fn chi_square(&self) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let expected = self.total as f64 / 256.0;
+        self.counts
+            .iter()
+            .map(|c| (*c as f64 - expected).powi(2) / expected)
+            .sum()
+    }
+
+    /// Calculate the Kullback-Leibler divergence D(self || reference) in bits, i.e. the bits per
+    /// byte wasted when coding the bytes counted here with a code optimised for `reference`.
+    ///
+    /// It is not symmetric and infinite if this histogram contains a byte the reference has
+    /// never seen; see [Histogram::js_divergence] for a bounded alternative.
+    /// It is 0 if either histogram is empty.
+    pub fn kl_divergence(&self, reference: &Histogram) -> f64 {
+        if self.is_empty() || reference.is_empty() {
+            return 0.0;
+        }
+        let (n, m) = (self.total as f64, reference.total as f64);
+        let d: f64 = self
+            .counts
+            .iter()
+            .zip(reference.counts.iter())
+            .filter(|(p, _)| **p > 0)
+            .map(|(p, q)| {
+                let p = *p as f64 / n;
+                p * (p / (*q as f64 / m)).log2()
+            })
+            .sum();
+        // rounding may push identical distributions slightly below zero
+        d.max(0.0)
+    }
+
+    /// Calculate the Jensen-Shannon divergence in bits, the mean KL divergence of both
+    /// histograms from their average distribution.
+    ///
+    /// Unlike [Histogram::kl_divergence] it is symmetric and bounded by 0 for identical and 1 for
+    /// disjoint distributions. It is 0 if either histogram is empty.
+    ///
+    /// # Example
+    ///```
+    /// use tropy::Histogram;
+    ///
+    /// let text = Histogram::from(&b"hello hello"[..]);
+    /// assert_eq!(text.js_divergence(&text), 0.0);
+    /// assert_eq!(text.js_divergence(&Histogram::from(&[0u8; 64][..])), 1.0);
+    ///```
+    pub fn js_divergence(&self, other: &Histogram) -> f64 {
+        if self.is_empty() || other.is_empty() {
+            return 0.0;
+        }
+        let (n, m) = (self.total as f64, other.total as f64);
+        let d: f64 = self
+            .counts
+            .iter()
+            .zip(other.counts.iter())
+            .map(|(p, q)| (*p as f64 / n, *q as f64 / m))
+            .map(|(p, q)| {
+                let mean = (p + q) / 2.0;
+                let term = |x: f64| if x > 0.0 { x * (x / mean).log2() } else { 0.0 };
+                (term(p) + term(q)) / 2.0
+            })
+            .sum();
+        d.clamp(0.0, 1.0)
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram::new()
+    }
+}
+
+/// Writes the 256 counts in the format read by [Histogram::from_str], 16 per line.
+impl Display for Histogram {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for row in self.counts.chunks(16) {
+            let row: Vec<String> = row.iter().map(|c| c.to_string()).collect();
+            writeln!(f, "{}", row.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Histogram {
+    type Err = Error;
+
+    /// Parses the counts of the byte values 0 to 255 separated by whitespace.
+    /// Everything after a `#` up to the end of the line is ignored.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let counts = s
+            .lines()
+            .flat_map(|line| line.split('#').next().unwrap_or("").split_whitespace())
+            .map(|c| {
+                c.parse::<u64>()
+                    .map_err(|_| Error::UnsupportedFormat(format!("Invalid count {:?}", c)))
+            })
+            .collect::<Result<Vec<u64>, Error>>()?;
+        if counts.len() != 256 {
+            return Err(Error::UnsupportedFormat(format!(
+                "Expected 256 counts, got {}",
+                counts.len()
+            )));
+        }
+        counts
+            .iter()
+            .try_fold(0u64, |sum, c| sum.checked_add(*c))
+            .ok_or(Error::Overflow)?;
+        let mut array = [0u64; 256];
+        array.copy_from_slice(&counts);
+        Ok(Histogram::from_counts(&array))
+    }
+}
+
+impl<'a> From<&'a [u8]> for Histogram {
+    fn from(input: &'a [u8]) -> Self {
+        let mut h = Histogram::new();
+        // a slice can't hold more than u64::MAX bytes
+        count(&mut h.counts, input);
+        h.total = input.len() as u64;
+        h
+    }
+}
+
+/// Inputs shorter than this are counted directly, merging the tables would cost more than it saves
+const INTERLEAVE_MIN: usize = 256;
+
+/// Add the occurrences of every byte value in `input` to `counts`.
+///
+/// Incrementing a single table stalls whenever neighbouring bytes are equal, as every increment
+/// has to wait for the previous store to the same counter. Spreading the bytes over four tables
+/// lets up to four increments proceed in parallel; the tables are added up once per call.
+fn count(counts: &mut [u64; 256], input: &[u8]) {
+    if input.len() < INTERLEAVE_MIN {
+        input.iter().for_each(|byte| counts[*byte as usize] += 1);
+        return;
+    }
+    // u32 counts can't overflow within a block
+    for block in input.chunks(u32::MAX as usize) {
+        let mut tables = [[0u32; 256]; 4];
+        let mut words = block.chunks_exact(4);
+        for w in &mut words {
+            tables[0][w[0] as usize] += 1;
+            tables[1][w[1] as usize] += 1;
+            tables[2][w[2] as usize] += 1;
+            tables[3][w[3] as usize] += 1;
+        }
+        words
+            .remainder()
+            .iter()
+            .for_each(|byte| tables[0][*byte as usize] += 1);
+        for (i, c) in counts.iter_mut().enumerate() {
+            *c += tables.iter().map(|t| t[i] as u64).sum::<u64>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{slice_entropy, Error, Histogram};
+
+    #[test]
+    fn merge_chunks() {
+        let data: Vec<u8> = (0u32..1000).map(|i| (i * i % 251) as u8).collect();
+        let mut whole = Histogram::new();
+        for chunk in data.chunks(64) {
+            whole.merge(&Histogram::from(chunk)).unwrap();
+        }
+
+        assert_eq!(whole, Histogram::from(&data[..]));
+        assert_eq!(whole.total(), 1000);
+        assert!((whole.entropy() - slice_entropy(&data)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn renyi() {
+        // uniform distributions have the same entropy for every order
+        let h = Histogram::from(&[0u8, 1, 2, 3][..]);
+        for &alpha in &[0.0, 0.5, 1.0, 2.0, 3.0, f64::INFINITY] {
+            assert!((h.renyi_entropy(alpha) - 2.0).abs() < 1e-12);
+        }
+
+        // p = (1/2, 1/4, 1/4)
+        let h = Histogram::from(&[0u8, 0, 1, 2][..]);
+        assert_eq!(h.min_entropy(), 1.0);
+        assert!((h.collision_entropy() - (8.0f64 / 3.0).log2()).abs() < 1e-12);
+        assert_eq!(h.renyi_entropy(0.0), 3.0f64.log2());
+        assert_eq!(h.renyi_entropy(1.0), 1.5);
+        // ordered from least to most conservative
+        assert!(h.renyi_entropy(0.5) > h.entropy());
+        assert!(h.entropy() > h.collision_entropy());
+        assert!(h.collision_entropy() > h.min_entropy());
+
+        assert_eq!(Histogram::new().min_entropy(), 0.0);
+        assert_eq!(Histogram::from(&[7u8; 10][..]).collision_entropy(), 0.0);
+    }
+
+    #[test]
+    fn subtract() {
+        let mut h = Histogram::from(&[0u8, 1, 1, 2, 2, 2][..]);
+        h.subtract(&Histogram::from(&[2u8, 1][..])).unwrap();
+        assert_eq!(h.count(0), 1);
+        assert_eq!(h.count(1), 1);
+        assert_eq!(h.count(2), 2);
+        assert_eq!(h.total(), 4);
+        assert_eq!(h.entropy(), 1.5);
+    }
+
+    #[test]
+    fn divergence() {
+        // p = (1/2, 1/2), q = (1/4, 3/4)
+        let p = Histogram::from(&[0u8, 1][..]);
+        let q = Histogram::from(&[0u8, 1, 1, 1][..]);
+        let kl = 0.5 * (2.0f64).log2() + 0.5 * (2.0f64 / 3.0).log2();
+        assert!((p.kl_divergence(&q) - kl).abs() < 1e-12);
+        assert!(p.kl_divergence(&q) != q.kl_divergence(&p));
+        assert_eq!(p.kl_divergence(&p), 0.0);
+        assert_eq!(q.kl_divergence(&Histogram::from(&[1u8][..])), f64::INFINITY);
+
+        assert_eq!(p.js_divergence(&q), q.js_divergence(&p));
+        assert!(p.js_divergence(&q) > 0.0 && p.js_divergence(&q) < kl);
+        assert_eq!(p.js_divergence(&Histogram::from(&[2u8, 3][..])), 1.0);
+        assert_eq!(p.js_divergence(&Histogram::new()), 0.0);
+    }
+
+    #[test]
+    fn text_format() {
+        let h = Histogram::from(&b"some bytes \x00\xff"[..]);
+        assert_eq!(h.to_string().parse::<Histogram>().unwrap(), h);
+
+        let mut s = "# a comment\n".to_string();
+        s.push_str(&"1 ".repeat(255));
+        s.push_str("3 # trailing comment");
+        let h: Histogram = s.parse().unwrap();
+        assert_eq!(h.total(), 258);
+        assert_eq!(h.count(255), 3);
+
+        assert!(matches!(
+            "1 2 3".parse::<Histogram>(),
+            Err(Error::UnsupportedFormat(_))
+        ));
+        assert!("x ".repeat(256).parse::<Histogram>().is_err());
+        let huge = format!("{} 1 {}", u64::MAX, "0 ".repeat(254));
+        assert!(matches!(huge.parse::<Histogram>(), Err(Error::Overflow)));
+    }
+
+    #[test]
+    fn subtract_foreign() {
+        let mut h = Histogram::from(&[0u8][..]);
+        assert!(h.subtract(&Histogram::from(&[1u8][..])).is_err());
+        assert_eq!(h, Histogram::from(&[0u8][..]));
+    }
+
+    #[test]
+    fn interleaved_counting() {
+        let data: Vec<u8> = (0u32..5000)
+            .map(|i| if i % 7 == 0 { 0 } else { (i * 31 % 251) as u8 })
+            .collect();
+        // around the threshold and with every remainder
+        for &len in &[0, 1, 255, 256, 257, 258, 259, 1000, 5000] {
+            let mut expected = [0u64; 256];
+            data[..len].iter().for_each(|b| expected[*b as usize] += 1);
+            let mut h = Histogram::from(&data[..len]);
+            assert_eq!(h.counts(), &expected, "{} bytes", len);
+            h.update(&data[..len]).unwrap();
+            assert_eq!(h.count(0), 2 * expected[0]);
+            assert_eq!(h.total(), 2 * len as u64);
+        }
+    }
+
+    #[test]
+    fn overflow() {
+        let mut h: Histogram = format!("{} {}", u64::MAX - 1, "0 ".repeat(255))
+            .parse()
+            .unwrap();
+        let before = h.clone();
+        assert!(matches!(h.update(&[1, 2]), Err(Error::Overflow)));
+        assert!(matches!(
+            h.merge(&Histogram::from(&[0u8, 0][..])),
+            Err(Error::Overflow)
+        ));
+        assert_eq!(h, before);
+        h.update(&[0]).unwrap();
+        assert_eq!(h.count(0), u64::MAX);
+    }
+}
@@ -1,4 +1,14 @@
+//! Entropy calculation for the command line and for embedded targets.
+//!
+//! Without the default `std` feature only the allocation-free core is available:
+//! [StackCalculator] and the integer-only [fixed_entropy]. With the `libm` feature providing the
+//! floating point functions, [Metric] and the entropy measures over arbitrary counts are
+//! available as well.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "std")]
 use std::io;
+#[cfg(feature = "std")]
 use std::io::Write;
 
 /// An entropy calculator
@@ -19,50 +29,62 @@ use std::io::Write;
 /// assert_eq!(e,1.0);
 ///
 ///```
+#[cfg(feature = "std")]
 pub struct Calculator {
-    // this is 2kB (256*8bytes)in size so it might blow the stack for certain configurations
-    // hence boxed
-    counts: Box<[u64; 256]>,
-    nbytes: usize,
+    hist: Histogram,
 }
 
+#[cfg(feature = "std")]
 impl Calculator {
     /// Instantiate a new calculator.
     pub fn new() -> Self {
         Calculator {
-            counts: Box::new([0u64; 256]),
-            nbytes: 0,
+            hist: Histogram::new(),
         }
     }
 
     /// Calculate Shannon entropy over the bytes written so far and clear the calculator's internal state.
     pub fn entropy(&mut self) -> f64 {
-        let bytes = self.nbytes as f64;
-        let e = self
-            .counts
-            .iter()
-            .cloned()
-            .filter(|c| c > &0u64)
-            .map(|c| c as f64 / bytes)
-            .map(|p| p * p.log2())
-            .fold(0.0, |h, x| h - x);
-
-        self.counts.iter_mut().for_each(|c| *c = 0u64);
-        self.nbytes = 0;
+        let e = self.hist.entropy();
+        self.hist.clear();
         e
     }
+
+    /// The histogram of the bytes written since the last call to [Calculator::entropy].
+    pub fn histogram(&self) -> &Histogram {
+        &self.hist
+    }
+
+    /// Copy the current histogram without resetting the calculator.
+    pub fn snapshot(&self) -> Histogram {
+        self.hist.clone()
+    }
+
+    /// Add the counts of a histogram, e.g. one collected by another calculator.
+    ///
+    /// Fails with [Error::Overflow] if the total would exceed a u64.
+    pub fn merge(&mut self, other: &Histogram) -> Result<(), Error> {
+        self.hist.merge(other)
+    }
+
+    /// Return the histogram and leave the calculator empty.
+    pub fn take_histogram(&mut self) -> Histogram {
+        std::mem::take(&mut self.hist)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for Calculator {
+    fn default() -> Self {
+        Calculator::new()
+    }
 }
 
+#[cfg(feature = "std")]
 impl Write for Calculator {
     fn write(&mut self, input: &[u8]) -> io::Result<usize> {
-        input.iter().for_each(|byte| {
-            self.counts[*byte as usize] = self.counts[*byte as usize]
-                .checked_add(1)
-                .expect("Count exceeded the length of a u64; Where'd you get that many bytes from?")
-        });
-        let bytes = input.len();
-        self.nbytes += bytes;
-        Ok(bytes)
+        self.hist.update(input)?;
+        Ok(input.len())
     }
 
     fn flush(&mut self) -> io::Result<()> {
@@ -72,14 +94,124 @@ impl Write for Calculator {
 
 /// Convenience method: Creates an [Calculator] fills it with the given input and returns the
 /// calculated entropy.
+#[cfg(feature = "std")]
 pub fn slice_entropy(input: &[u8]) -> f64 {
-    let mut c = Calculator::new();
+    Histogram::from(input).entropy()
+}
 
-    c.write_all(input)
-        .expect("Writing bytes to the calculator cannot fail");
+mod error;
+pub use error::Error;
 
-    c.entropy()
-}
+mod fixed;
+pub use fixed::{fixed_entropy, slice_entropy_fixed, FIXED_ONE};
+
+#[cfg(any(feature = "std", feature = "libm"))]
+mod math;
+
+#[cfg(feature = "std")]
+mod chunked;
+#[cfg(feature = "std")]
+pub use chunked::{ChunkedEntropy, LastChunk};
+
+#[cfg(feature = "std")]
+mod classify;
+#[cfg(feature = "std")]
+pub use classify::{classify, Class};
+
+#[cfg(feature = "std")]
+mod compare;
+#[cfg(feature = "std")]
+pub use compare::{differences, Difference};
+
+#[cfg(feature = "std")]
+mod elf;
+#[cfg(feature = "std")]
+pub use elf::{Elf, ElfPart, ElfReport, Section, Segment};
+
+#[cfg(feature = "std")]
+mod ent;
+#[cfg(feature = "std")]
+pub use ent::{EntCalculator, EntReport};
+
+#[cfg(feature = "std")]
+mod estimator;
+#[cfg(feature = "std")]
+pub use estimator::Estimator;
+
+#[cfg(feature = "std")]
+mod histogram;
+#[cfg(feature = "std")]
+pub use histogram::Histogram;
+
+#[cfg(feature = "std")]
+mod lz;
+#[cfg(feature = "std")]
+pub use lz::{slice_lz_complexity, LzCalculator};
+
+#[cfg(feature = "std")]
+mod markov;
+#[cfg(feature = "std")]
+pub use markov::{slice_markov_entropy, MarkovCalculator, MarkovReport};
+
+#[cfg(any(feature = "std", feature = "libm"))]
+mod metric;
+#[cfg(any(feature = "std", feature = "libm"))]
+pub use metric::Metric;
+
+#[cfg(feature = "std")]
+mod parallel;
+#[cfg(feature = "std")]
+pub use parallel::ParallelScan;
+
+#[cfg(feature = "std")]
+mod profile;
+#[cfg(feature = "std")]
+pub use profile::Profile;
+
+#[cfg(feature = "std")]
+mod report;
+#[cfg(feature = "std")]
+pub use report::{ChunkRecord, Input, ScanReport, Summary};
+
+#[cfg(feature = "std")]
+mod rolling;
+#[cfg(feature = "std")]
+pub use rolling::RollingCalculator;
+
+#[cfg(feature = "std")]
+mod rules;
+#[cfg(feature = "std")]
+pub use rules::{Rule, RuleSet, MAX_RULE_WINDOW};
+
+#[cfg(feature = "std")]
+mod segment;
+#[cfg(feature = "std")]
+pub use segment::{segment, Region, DEFAULT_PENALTY};
+
+#[cfg(feature = "std")]
+mod signature;
+#[cfg(feature = "std")]
+pub use signature::{
+    find_signatures, Hit, Signature, SignatureScanner, SIGNATURES, SIGNATURE_WINDOW,
+};
+
+mod stack;
+pub use stack::StackCalculator;
+
+#[cfg(feature = "std")]
+mod symbol;
+#[cfg(feature = "std")]
+pub use symbol::{Endian, Symbol, SymbolCalculator};
+
+#[cfg(feature = "std")]
+mod uniformity;
+#[cfg(feature = "std")]
+pub use uniformity::{Uniformity, UniformityTest};
+
+#[cfg(feature = "async")]
+mod stream;
+#[cfg(feature = "async")]
+pub use stream::AsyncChunkedEntropy;
 
 /// Print coloured output using ANSI escape sequences.
 /// The terminal in use must support it.
@@ -98,11 +230,40 @@ pub fn slice_entropy(input: &[u8]) -> f64 {
 /// println!("{}", Rgb::from(some_hsl_colour).fg("This is some HSL colour"));
 ///
 /// ```
+#[cfg(feature = "std")]
 pub mod colour;
 
-#[cfg(test)]
+/// Helpers shared by the tests of several modules
+#[cfg(all(test, feature = "std"))]
+mod test_util {
+    /// The seed of [noise]
+    pub const SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+    /// The xorshift64 sequence following `seed`, good enough to look random to the tests
+    pub fn xorshift(seed: u64) -> impl Iterator<Item = u64> {
+        std::iter::successors(Some(seed), |&x| {
+            let x = x ^ (x << 13);
+            let x = x ^ (x >> 7);
+            Some(x ^ (x << 17))
+        })
+        .skip(1)
+    }
+
+    /// `n` bytes which look random, the same ones on every call
+    pub fn noise(n: usize) -> Vec<u8> {
+        seeded_noise(n, SEED)
+    }
+
+    /// Like [noise], but different bytes for every seed
+    pub fn seeded_noise(n: usize, seed: u64) -> Vec<u8> {
+        xorshift(seed).map(|x| (x >> 32) as u8).take(n).collect()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
 mod test {
-    use crate::slice_entropy;
+    use crate::{slice_entropy, Calculator};
+    use std::io::Write;
 
     #[test]
     fn simple() {
@@ -119,4 +280,21 @@ mod test {
         let e = slice_entropy(&x);
         assert_eq!(e, 1.0);
     }
+
+    #[test]
+    fn snapshot() {
+        let mut a = Calculator::new();
+        let mut b = Calculator::new();
+        a.write_all(&[0u8, 1u8]).unwrap();
+        b.write_all(&[2u8, 3u8]).unwrap();
+
+        let h = a.snapshot();
+        assert_eq!(a.histogram(), &h);
+        assert_eq!(h.entropy(), 1.0);
+
+        a.merge(&b.take_histogram()).unwrap();
+        assert!(b.histogram().is_empty());
+        assert_eq!(a.entropy(), 2.0);
+        assert!(a.histogram().is_empty());
+    }
 }
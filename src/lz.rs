@@ -0,0 +1,215 @@
+use std::io;
+use std::io::Write;
+
+// shorter matches never pay off against literals
+const MIN_MATCH: usize = 3;
+// candidates tried per position, bounds the time spent on highly repetitive data
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 15;
+
+/// Estimates how well the bytes written so far compress, in bits per byte.
+///
+/// The input is parsed greedily into literals and back-references to any earlier position,
+/// like LZ77 with an unbounded window. A literal costs 8 bits and a reference the bits needed to
+/// address the earlier position plus the Elias-gamma code of its length. The result is the
+/// total cost divided by the number of bytes, capped at 8.
+///
+/// Unlike the byte histogram, this catches repetition at any scale: a random block repeated
+/// once has an entropy of 8 bits per byte but compresses to half its size.
+///
+/// All bytes are kept until [LzCalculator::clear], so it is meant for chunks rather than whole
+/// files.
+///
+/// # Example
+///```
+/// use std::io::Write;
+/// use tropy::{slice_entropy, LzCalculator};
+///
+/// let block: Vec<u8> = (0..4096u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+///
+/// let mut c = LzCalculator::new();
+/// c.write_all(&block).unwrap();
+/// c.write_all(&block).unwrap();
+///
+/// assert!(c.complexity() < 4.5);
+/// assert!(slice_entropy(&[&block[..], &block[..]].concat()) > 7.9);
+///```
+#[derive(Debug, Clone, Default)]
+pub struct LzCalculator {
+    data: Vec<u8>,
+}
+
+impl LzCalculator {
+    /// Instantiate a new calculator.
+    pub fn new() -> Self {
+        LzCalculator { data: Vec::new() }
+    }
+
+    /// Append the given bytes.
+    pub fn update(&mut self, input: &[u8]) {
+        self.data.extend_from_slice(input);
+    }
+
+    /// The number of bytes written so far.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Whether no bytes have been written.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Calculate the estimated compressed size in bits per byte, between 0 and 8.
+    pub fn complexity(&self) -> f64 {
+        if self.data.is_empty() {
+            return 0.0;
+        }
+        let bits = parse(&self.data);
+        (bits as f64 / self.data.len() as f64).min(8.0)
+    }
+
+    /// Drop all bytes.
+    pub fn clear(&mut self) {
+        self.data.clear();
+    }
+}
+
+impl Write for LzCalculator {
+    fn write(&mut self, input: &[u8]) -> io::Result<usize> {
+        self.update(input);
+        Ok(input.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Convenience method: Creates an [LzCalculator], fills it with the given input and returns the
+/// estimated compressed size in bits per byte.
+pub fn slice_lz_complexity(input: &[u8]) -> f64 {
+    let mut c = LzCalculator::new();
+    c.update(input);
+    c.complexity()
+}
+
+/// Greedy LZ77 parse, returns the cost of the encoding in bits
+fn parse(data: &[u8]) -> u64 {
+    // most recent position of every hash, and the previous one with the same hash per position,
+    // usize::MAX for none as no slice is that long
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; data.len()];
+
+    let mut bits = 0u64;
+    let mut pos = 0;
+    while pos < data.len() {
+        let (len, cost) = longest_match(data, pos, &head, &prev)
+            .map(|len| (len, reference_bits(pos, len)))
+            .filter(|(len, cost)| *cost < 8 * *len as u64)
+            .unwrap_or((1, 8));
+        bits += cost;
+        for p in pos..pos + len {
+            if p + MIN_MATCH <= data.len() {
+                let h = hash(&data[p..p + MIN_MATCH]);
+                prev[p] = head[h];
+                head[h] = p;
+            }
+        }
+        pos += len;
+    }
+    bits
+}
+
+/// The length of the longest earlier match of the bytes at `pos`, if there is one of at least
+/// [MIN_MATCH] bytes
+fn longest_match(data: &[u8], pos: usize, head: &[usize], prev: &[usize]) -> Option<usize> {
+    if pos + MIN_MATCH > data.len() {
+        return None;
+    }
+    let mut best = 0;
+    let mut candidate = head[hash(&data[pos..pos + MIN_MATCH])];
+    for _ in 0..MAX_CHAIN {
+        if candidate == usize::MAX {
+            break;
+        }
+        let start = candidate;
+        // the match may overlap the current position, like a run
+        let len = data[pos..]
+            .iter()
+            .zip(&data[start..])
+            .take_while(|(a, b)| a == b)
+            .count();
+        if len > best {
+            best = len;
+            if pos + len == data.len() {
+                break;
+            }
+        }
+        candidate = prev[start];
+    }
+    if best >= MIN_MATCH {
+        Some(best)
+    } else {
+        None
+    }
+}
+
+/// Bits for a reference from `pos` back to one of the `pos` earlier positions, copying `len` bytes
+fn reference_bits(pos: usize, len: usize) -> u64 {
+    let distance = bit_length(pos as u64);
+    // Elias gamma
+    let length = 2 * bit_length(len as u64) - 1;
+    distance + length
+}
+
+fn bit_length(x: u64) -> u64 {
+    64 - x.leading_zeros() as u64
+}
+
+fn hash(bytes: &[u8]) -> usize {
+    let x = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+    (x.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_util::noise;
+    use crate::{slice_entropy, slice_lz_complexity, LzCalculator};
+
+    #[test]
+    fn bounds() {
+        assert_eq!(slice_lz_complexity(&[]), 0.0);
+        assert_eq!(slice_lz_complexity(&[42]), 8.0);
+        // random data doesn't compress
+        assert!(slice_lz_complexity(&noise(8192)) > 7.9);
+        // a run is a single reference after the first byte
+        assert!(slice_lz_complexity(&[0u8; 4096]) < 0.01);
+    }
+
+    #[test]
+    fn repetition() {
+        let block = noise(4096);
+        let twice = [&block[..], &block[..]].concat();
+        assert!(slice_entropy(&twice) > 7.9);
+        let c = slice_lz_complexity(&twice);
+        assert!((c - 4.0).abs() < 0.1, "{}", c);
+
+        // a counter has a flat histogram, but repeats after 256 bytes
+        let counter: Vec<u8> = (0..=255u8).cycle().take(8192).collect();
+        assert!(slice_lz_complexity(&counter) < 0.5);
+    }
+
+    #[test]
+    fn streaming() {
+        let data = noise(3000);
+        let mut c = LzCalculator::new();
+        data.chunks(7).for_each(|chunk| c.update(chunk));
+        assert_eq!(c.len(), 3000);
+        assert_eq!(c.complexity(), slice_lz_complexity(&data));
+
+        c.clear();
+        assert!(c.is_empty());
+        assert_eq!(c.complexity(), 0.0);
+    }
+}
@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+
+use crate::Error;
+
+/// Calculates the conditional entropy of a byte given the `order` bytes before it, i.e. the
+/// entropy rate of an order-k Markov model of the input.
+///
+/// Order 0 is the plain Shannon entropy, order 1 uses bigrams and order 2 trigrams.
+/// Data with a flat byte histogram but predictable sequences (counters, lookup tables) has a high
+/// order-0 entropy but a low conditional entropy.
+///
+/// The n-grams are counted in a hash map, so the memory used grows with the number of distinct
+/// n-grams rather than the number of possible ones.
+///
+/// # Example
+///```
+/// use std::io::Write;
+/// use tropy::MarkovCalculator;
+///
+/// let counter: Vec<u8> = (0..=255u8).cycle().take(4096).collect();
+///
+/// let mut c = MarkovCalculator::new(1).unwrap();
+/// c.write_all(&counter).unwrap();
+/// // every byte is perfectly predicted by the one before it
+/// assert_eq!(c.entropy(), 0.0);
+///```
+#[derive(Debug, Clone)]
+pub struct MarkovCalculator {
+    order: usize,
+    // the context followed by the byte, packed big-endian
+    grams: HashMap<u32, u64>,
+    context: u32,
+    // the number of bytes in the context, up to the order
+    seen: usize,
+}
+
+impl MarkovCalculator {
+    /// The highest supported order; the context plus the predicted byte must fit into a u32
+    pub const MAX_ORDER: usize = 3;
+
+    /// Instantiate a new calculator of the given order.
+    ///
+    /// Fails with [Error::InvalidArgument] if `order` is larger than
+    /// [MarkovCalculator::MAX_ORDER].
+    pub fn new(order: usize) -> Result<Self, Error> {
+        if order > Self::MAX_ORDER {
+            return Err(Error::InvalidArgument(format!(
+                "Markov models are supported up to order {}",
+                Self::MAX_ORDER
+            )));
+        }
+        Ok(MarkovCalculator {
+            order,
+            grams: HashMap::new(),
+            context: 0,
+            seen: 0,
+        })
+    }
+
+    /// The order of the model.
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    /// Count the n-grams in the given bytes, continuing the context of previous updates.
+    pub fn update(&mut self, input: &[u8]) {
+        let context_mask = mask(self.order);
+        let gram_mask = mask(self.order + 1);
+        for b in input {
+            let gram = ((self.context << 8) | *b as u32) & gram_mask;
+            if self.seen == self.order {
+                *self.grams.entry(gram).or_insert(0) += 1;
+            } else {
+                self.seen += 1;
+            }
+            self.context = gram & context_mask;
+        }
+    }
+
+    /// Calculate the conditional entropy in bits per byte over the bytes written so far.
+    ///
+    /// The n-grams stay counted, so more bytes can follow with the same context.
+    pub fn entropy(&self) -> f64 {
+        let mut contexts: HashMap<u32, u64> = HashMap::new();
+        for (gram, count) in self.grams.iter() {
+            *contexts.entry(gram >> 8).or_insert(0) += count;
+        }
+        let e = plugin_entropy(self.grams.values()) - plugin_entropy(contexts.values());
+        // rounding may push perfectly predictable data slightly below zero
+        e.max(0.0)
+    }
+
+    /// Drop all counts and the context.
+    pub fn clear(&mut self) {
+        self.grams.clear();
+        self.context = 0;
+        self.seen = 0;
+    }
+}
+
+impl Write for MarkovCalculator {
+    fn write(&mut self, input: &[u8]) -> io::Result<usize> {
+        self.update(input);
+        Ok(input.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Convenience method: Creates a [MarkovCalculator] of the given order, fills it with the input
+/// and returns the conditional entropy.
+///
+/// Fails like [MarkovCalculator::new].
+pub fn slice_markov_entropy(input: &[u8], order: usize) -> Result<f64, Error> {
+    let mut c = MarkovCalculator::new(order)?;
+    c.update(input);
+    Ok(c.entropy())
+}
+
+/// The order-0 and conditional entropy of a chunk, along with the conditional entropy of the same
+/// bytes in random order.
+///
+/// Conditional entropy estimates from a few kB are far below 8 bits even for random data,
+/// because most n-grams are only seen once. Shuffling the chunk keeps its byte histogram and
+/// sample size but destroys any sequential structure, so it is the baseline to compare against.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MarkovReport {
+    /// The order of the model
+    pub order: usize,
+    /// Shannon entropy of the bytes in bits per byte
+    pub order0: f64,
+    /// Conditional entropy in bits per byte
+    pub conditional: f64,
+    /// Conditional entropy of the shuffled bytes in bits per byte
+    pub shuffled: f64,
+}
+
+impl MarkovReport {
+    /// Order-0 entropy from which a chunk counts as high entropy for [MarkovReport::is_structured]
+    pub const HIGH_ENTROPY: f64 = 6.0;
+    /// Fraction of the shuffled conditional entropy which the context must explain for
+    /// [MarkovReport::is_structured]
+    pub const STRUCTURE: f64 = 0.1;
+
+    /// Evaluate the given bytes with a model of the given order.
+    ///
+    /// Fails like [MarkovCalculator::new].
+    pub fn of(input: &[u8], order: usize) -> Result<Self, Error> {
+        let conditional = slice_markov_entropy(input, order)?;
+        let mut shuffled = input.to_vec();
+        shuffle(&mut shuffled);
+        Ok(MarkovReport {
+            order,
+            order0: crate::slice_entropy(input),
+            conditional,
+            shuffled: slice_markov_entropy(&shuffled, order)?,
+        })
+    }
+
+    /// The fraction of the entropy explained by the preceding bytes beyond what random ordering
+    /// would give, 0 for random data and up to 1 for perfectly predictable data.
+    pub fn structure(&self) -> f64 {
+        if self.shuffled > 0.0 {
+            (1.0 - self.conditional / self.shuffled).max(0.0)
+        } else {
+            0.0
+        }
+    }
+
+    /// Whether the bytes have a high order-0 entropy but are predictable from their context,
+    /// i.e. look random by their histogram but are structured rather than encrypted or
+    /// compressed.
+    pub fn is_structured(&self) -> bool {
+        self.order0 >= Self::HIGH_ENTROPY && self.structure() >= Self::STRUCTURE
+    }
+}
+
+/// Deterministic Fisher-Yates shuffle, so the same input always gives the same report
+fn shuffle(data: &mut [u8]) {
+    let mut x = 0x9e37_79b9_7f4a_7c15u64;
+    for i in (1..data.len()).rev() {
+        // xorshift64
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        let j = (x % (i as u64 + 1)) as usize;
+        data.swap(i, j);
+    }
+}
+
+fn mask(bytes: usize) -> u32 {
+    if bytes >= 4 {
+        u32::MAX
+    } else {
+        (1u32 << (8 * bytes)) - 1
+    }
+}
+
+fn plugin_entropy<'a, I: Iterator<Item = &'a u64>>(counts: I) -> f64 {
+    let mut counts: Vec<u64> = counts.cloned().collect();
+    // hash map order varies between runs, a fixed summation order keeps the result reproducible
+    counts.sort_unstable();
+    let total: u64 = counts.iter().sum();
+    let total = total as f64;
+    counts
+        .iter()
+        .map(|c| *c as f64 / total)
+        .map(|p| p * p.log2())
+        .fold(0.0, |h, x| h - x)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_util::noise;
+    use crate::{slice_entropy, slice_markov_entropy, Error, MarkovCalculator, MarkovReport};
+
+    #[test]
+    fn order0_is_shannon() {
+        let data = noise(5000);
+        assert!((slice_markov_entropy(&data, 0).unwrap() - slice_entropy(&data)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn known_values() {
+        // alternating bytes: one bit of order-0 entropy, nothing left given the previous byte
+        let data: Vec<u8> = [0u8, 1].iter().cycle().take(1000).cloned().collect();
+        assert!((slice_markov_entropy(&data, 0).unwrap() - 1.0).abs() < 1e-9);
+        assert_eq!(slice_markov_entropy(&data, 1).unwrap(), 0.0);
+
+        // 0 0 1 1 repeated: the previous byte leaves a coin flip, two of them determine the next
+        let data: Vec<u8> = [0u8, 0, 1, 1].iter().cycle().take(1000).cloned().collect();
+        assert!((slice_markov_entropy(&data, 1).unwrap() - 1.0).abs() < 1e-2);
+        assert_eq!(slice_markov_entropy(&data, 2).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn streaming() {
+        let data = noise(3000);
+        let mut c = MarkovCalculator::new(2).unwrap();
+        data.chunks(7).for_each(|chunk| c.update(chunk));
+        assert!((c.entropy() - slice_markov_entropy(&data, 2).unwrap()).abs() < 1e-9);
+
+        c.clear();
+        assert_eq!(c.entropy(), 0.0);
+    }
+
+    #[test]
+    fn structured() {
+        // a counter has a perfectly flat histogram
+        let counter: Vec<u8> = (0..=255u8).cycle().take(4096).collect();
+        let r = MarkovReport::of(&counter, 1).unwrap();
+        assert_eq!(r.order0, 8.0);
+        assert_eq!(r.conditional, 0.0);
+        assert!(r.is_structured());
+
+        for order in 1..=3 {
+            let r = MarkovReport::of(&noise(4096), order).unwrap();
+            assert!(r.order0 > 7.9);
+            assert!(!r.is_structured(), "{:?}", r);
+        }
+
+        // low entropy data is not flagged, the map already shows it
+        let r = MarkovReport::of(&[0u8; 4096], 1).unwrap();
+        assert!(!r.is_structured());
+    }
+
+    #[test]
+    fn order_too_large() {
+        assert!(matches!(
+            MarkovCalculator::new(4),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(MarkovReport::of(&[0u8; 16], 4).is_err());
+    }
+}
@@ -0,0 +1,21 @@
+//! Floating point functions which core doesn't provide; libm stands in without std.
+
+#[cfg(feature = "std")]
+pub(crate) fn log2(x: f64) -> f64 {
+    x.log2()
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn log2(x: f64) -> f64 {
+    libm::log2(x)
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn powf(x: f64, y: f64) -> f64 {
+    x.powf(y)
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn powf(x: f64, y: f64) -> f64 {
+    libm::pow(x, y)
+}
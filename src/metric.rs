@@ -0,0 +1,199 @@
+use core::fmt;
+use core::fmt::{Display, Formatter};
+#[cfg(feature = "std")]
+use std::str::FromStr;
+
+use crate::math::{log2, powf};
+#[cfg(feature = "std")]
+use crate::{Error, Histogram};
+
+/// The entropy measure calculated for each chunk.
+///
+/// All of them are in bits per byte, i.e. between 0 and 8.
+///
+/// # Example
+///```
+/// use tropy::Metric;
+///
+/// // two of the four bytes are the same
+/// let counts = [2u64, 1, 1];
+/// assert_eq!(Metric::Shannon.of_counts(counts.iter().cloned(), 4), 1.5);
+/// assert_eq!(Metric::Min.of_counts(counts.iter().cloned(), 4), 1.0);
+///```
+///
+/// With `std`, metrics can be parsed and calculated over a [Histogram]:
+///```
+/// # #[cfg(feature = "std")]
+/// # {
+/// use tropy::{Histogram, Metric};
+///
+/// let m: Metric = "renyi:2".parse().unwrap();
+/// assert_eq!(m, Metric::Collision);
+///
+/// let h = Histogram::from(&[0u8, 0, 1, 2][..]);
+/// assert_eq!(m.of(&h), h.collision_entropy());
+/// # }
+///```
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Metric {
+    /// Shannon entropy, see [Histogram::entropy]
+    #[default]
+    Shannon,
+    /// Min-entropy, see [Histogram::min_entropy]
+    Min,
+    /// Collision entropy, see [Histogram::collision_entropy]
+    Collision,
+    /// Rényi entropy of the given order, see [Histogram::renyi_entropy]
+    Renyi(f64),
+}
+
+impl Metric {
+    /// Calculate this measure over the given histogram.
+    #[cfg(feature = "std")]
+    pub fn of(&self, h: &Histogram) -> f64 {
+        self.of_counts(h.counts().iter().cloned(), h.total())
+    }
+
+    /// Calculate this measure in bits per symbol over the occurrence counts of an arbitrary
+    /// alphabet, e.g. the one of a [crate::SymbolCalculator]. `total` is the sum of the counts.
+    pub fn of_counts<I>(&self, counts: I, total: u64) -> f64
+    where
+        I: Iterator<Item = u64> + Clone,
+    {
+        match self {
+            Metric::Shannon => shannon(counts, total),
+            Metric::Min => min(counts, total),
+            Metric::Collision => renyi(counts, total, 2.0),
+            Metric::Renyi(alpha) => renyi(counts, total, *alpha),
+        }
+    }
+}
+
+pub(crate) fn shannon<I: Iterator<Item = u64>>(counts: I, total: u64) -> f64 {
+    let total = total as f64;
+    counts
+        .filter(|c| c > &0u64)
+        .map(|c| c as f64 / total)
+        .map(|p| p * log2(p))
+        .fold(0.0, |h, x| h - x)
+}
+
+pub(crate) fn min<I: Iterator<Item = u64>>(counts: I, total: u64) -> f64 {
+    match counts.max() {
+        Some(max) if max > 0 => -log2(max as f64 / total as f64),
+        _ => 0.0,
+    }
+}
+
+pub(crate) fn renyi<I>(counts: I, total: u64, alpha: f64) -> f64
+where
+    I: Iterator<Item = u64> + Clone,
+{
+    if alpha.is_nan() || alpha < 0.0 {
+        return f64::NAN;
+    }
+    if total == 0 {
+        return 0.0;
+    }
+    if alpha == 1.0 {
+        return shannon(counts, total);
+    }
+    if alpha.is_infinite() {
+        return min(counts, total);
+    }
+
+    let total = total as f64;
+    let sum: f64 = counts
+        .filter(|c| c > &0u64)
+        .map(|c| powf(c as f64 / total, alpha))
+        .sum();
+    let e = log2(sum) / (1.0 - alpha);
+    // rounding may push a single repeated symbol slightly below zero
+    e.max(0.0)
+}
+
+impl Display for Metric {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Metric::Shannon => write!(f, "shannon"),
+            Metric::Min => write!(f, "min"),
+            Metric::Collision => write!(f, "collision"),
+            Metric::Renyi(alpha) => write!(f, "renyi:{}", alpha),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl FromStr for Metric {
+    type Err = Error;
+
+    /// Parses `shannon`, `min`, `collision` or `renyi:<order>`.
+    /// Orders with a dedicated variant are normalised to it.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "shannon" => Ok(Metric::Shannon),
+            "min" => Ok(Metric::Min),
+            "collision" => Ok(Metric::Collision),
+            _ => {
+                let alpha = s
+                    .strip_prefix("renyi:")
+                    .ok_or_else(|| Error::UnsupportedFormat(format!("Unknown metric {:?}", s)))?;
+                let alpha: f64 = alpha.parse().map_err(|_| {
+                    Error::UnsupportedFormat(format!(
+                        "Invalid order of the Rényi entropy {:?}",
+                        alpha
+                    ))
+                })?;
+                if alpha.is_nan() || alpha < 0.0 {
+                    Err(Error::InvalidArgument(format!(
+                        "The order of the Rényi entropy must not be negative: {}",
+                        alpha
+                    )))
+                } else if alpha == 1.0 {
+                    Ok(Metric::Shannon)
+                } else if alpha == 2.0 {
+                    Ok(Metric::Collision)
+                } else if alpha.is_infinite() {
+                    Ok(Metric::Min)
+                } else {
+                    Ok(Metric::Renyi(alpha))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::{Error, Histogram, Metric};
+
+    #[test]
+    fn parse() {
+        assert_eq!("shannon".parse::<Metric>().unwrap(), Metric::Shannon);
+        assert_eq!("min".parse::<Metric>().unwrap(), Metric::Min);
+        assert_eq!("renyi:inf".parse::<Metric>().unwrap(), Metric::Min);
+        assert_eq!("renyi:1".parse::<Metric>().unwrap(), Metric::Shannon);
+        assert_eq!("renyi:0.5".parse::<Metric>().unwrap(), Metric::Renyi(0.5));
+        assert!(matches!(
+            "renyi:-1".parse::<Metric>(),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            "renyi:".parse::<Metric>(),
+            Err(Error::UnsupportedFormat(_))
+        ));
+        assert!("bogus".parse::<Metric>().is_err());
+        assert!(Metric::Renyi(-1.0)
+            .of(&Histogram::from(&[1u8][..]))
+            .is_nan());
+
+        for m in &[
+            Metric::Shannon,
+            Metric::Min,
+            Metric::Collision,
+            Metric::Renyi(3.5),
+        ] {
+            assert_eq!(&m.to_string().parse::<Metric>().unwrap(), m);
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+
+use crate::Error;
+
+// workers read this many bytes at once (rounded to whole chunks) to keep the number of seeks low
+const BATCH_BYTES: usize = 1 << 20;
+// but small chunks with large results (e.g. histograms) would use a lot of memory in flight
+const BATCH_CHUNKS: usize = 1024;
+// batches a worker may compute ahead of the consumer
+const BATCHES_IN_FLIGHT: usize = 2;
+
+type Batch<T> = io::Result<Vec<T>>;
+
+/// Computes a value for each chunk of a seekable input on a pool of worker threads.
+///
+/// The input is split into consecutive, disjoint chunks of a fixed size. Runs of chunks are
+/// handed to the workers round-robin, each of which reads them through its own reader.
+/// The results are yielded in offset order as `(offset, value)`, exactly as a sequential
+/// scan would produce them; a trailing partial chunk is dropped.
+///
+/// # Example
+///```
+/// use std::io::Cursor;
+/// use std::sync::Arc;
+/// use tropy::{slice_entropy, ParallelScan};
+///
+/// let data: Arc<[u8]> = (0..4096u32).map(|i| (i % 7) as u8).collect::<Vec<_>>().into();
+/// let len = data.len() as u64;
+/// let src = data.clone();
+///
+/// let scan = ParallelScan::new(move || Ok(Cursor::new(src.clone())), len, 1024, 4, slice_entropy)
+///     .unwrap();
+/// let entropies: Vec<(u64, f64)> = scan.map(|r| r.unwrap()).collect();
+///
+/// assert_eq!(entropies.len(), 4);
+/// assert_eq!(entropies[3].0, 3072);
+///```
+pub struct ParallelScan<T> {
+    // one channel per worker; batch n is computed by worker n % workers
+    results: Vec<Receiver<Batch<T>>>,
+    workers: Vec<JoinHandle<()>>,
+    chunksize: usize,
+    batch: usize,
+    // index of the next batch to be taken from the workers
+    next: usize,
+    batches: usize,
+    current: std::vec::IntoIter<T>,
+    // offset of the next item in `current`
+    offset: u64,
+    failed: bool,
+}
+
+impl<T: Send + 'static> ParallelScan<T> {
+    /// Scan an input of `len` bytes in chunks of `chunksize` bytes using `threads` workers.
+    ///
+    /// `open` is called once per worker to get an independent reader over the input.
+    /// `f` is applied to the bytes of every chunk.
+    ///
+    /// Fails with [Error::ZeroLength] if `chunksize` or `threads` is zero and with
+    /// [Error::Overflow] if the chunks can't be counted in a usize.
+    pub fn new<R, O, F>(
+        open: O,
+        len: u64,
+        chunksize: usize,
+        threads: usize,
+        f: F,
+    ) -> Result<Self, Error>
+    where
+        R: Read + Seek,
+        O: Fn() -> io::Result<R> + Send + Sync + 'static,
+        F: Fn(&[u8]) -> T + Send + Sync + 'static,
+    {
+        if chunksize == 0 {
+            return Err(Error::ZeroLength("chunk size"));
+        }
+        if threads == 0 {
+            return Err(Error::ZeroLength("number of threads"));
+        }
+
+        let chunks = usize::try_from(len / chunksize as u64).map_err(|_| Error::Overflow)?;
+        let batch = (BATCH_BYTES / chunksize).clamp(1, BATCH_CHUNKS);
+        let batches = chunks.div_ceil(batch);
+        let open = Arc::new(open);
+        let f = Arc::new(f);
+
+        let workers = threads.min(batches);
+        let (results, workers) = (0..workers)
+            .map(|worker| {
+                let (tx, rx) = sync_channel(BATCHES_IN_FLIGHT);
+                let open = open.clone();
+                let f = f.clone();
+                let handle = thread::spawn(move || {
+                    let mut r = match open() {
+                        Ok(r) => r,
+                        Err(e) => {
+                            let _ = tx.send(Err(e));
+                            return;
+                        }
+                    };
+                    let mut buf = vec![0u8; batch * chunksize];
+                    for b in (worker..batches).step_by(workers) {
+                        let first = b * batch;
+                        let n = batch.min(chunks - first);
+                        let buf = &mut buf[..n * chunksize];
+                        let res = r
+                            .seek(SeekFrom::Start(first as u64 * chunksize as u64))
+                            .and_then(|_| r.read_exact(buf))
+                            .map(|_| buf.chunks(chunksize).map(|c| f(c)).collect());
+                        let failed = res.is_err();
+                        // the consumer hung up, nobody is interested in the rest
+                        if tx.send(res).is_err() || failed {
+                            return;
+                        }
+                    }
+                });
+                (rx, handle)
+            })
+            .unzip();
+
+        Ok(ParallelScan {
+            results,
+            workers,
+            chunksize,
+            batch,
+            next: 0,
+            batches,
+            current: Vec::new().into_iter(),
+            offset: 0,
+            failed: false,
+        })
+    }
+
+    /// Scan the file at `path` in chunks of `chunksize` bytes using `threads` workers, each of
+    /// which opens the file on its own.
+    pub fn file<P, F>(path: P, chunksize: usize, threads: usize, f: F) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+        F: Fn(&[u8]) -> T + Send + Sync + 'static,
+    {
+        let path = path.as_ref().to_path_buf();
+        let len = File::open(&path)?.metadata()?.len();
+        ParallelScan::new(move || File::open(&path), len, chunksize, threads, f)
+    }
+}
+
+impl<T> Iterator for ParallelScan<T> {
+    type Item = Result<(u64, T), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        loop {
+            if let Some(value) = self.current.next() {
+                let offset = self.offset;
+                self.offset += self.chunksize as u64;
+                return Some(Ok((offset, value)));
+            }
+            if self.next == self.batches {
+                return None;
+            }
+            let worker = self.next % self.results.len();
+            match self.results[worker].recv() {
+                Ok(Ok(values)) => {
+                    // in u64, files beyond 4GiB overflow a 32 bit usize
+                    self.offset = (self.next * self.batch) as u64 * self.chunksize as u64;
+                    self.current = values.into_iter();
+                    self.next += 1;
+                }
+                Ok(Err(e)) => {
+                    self.failed = true;
+                    return Some(Err(e.into()));
+                }
+                // all batches are accounted for, so the worker can only have hung up by panicking
+                Err(_) => {
+                    self.failed = true;
+                    return Some(Err(io::Error::other("A worker thread panicked").into()));
+                }
+            }
+        }
+    }
+}
+
+impl<T> Drop for ParallelScan<T> {
+    fn drop(&mut self) {
+        // hanging up makes blocked workers return
+        self.results.clear();
+        self.workers.drain(..).for_each(|w| {
+            let _ = w.join();
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_util::noise;
+    use crate::{slice_entropy, Error, ParallelScan};
+    use std::io::Cursor;
+    use std::sync::Arc;
+
+    fn data() -> Arc<[u8]> {
+        noise(100_000).into()
+    }
+
+    #[test]
+    fn same_as_sequential() {
+        let data = data();
+        for &(chunksize, threads) in &[(1024, 4), (100, 3), (7, 1), (300_000, 2), (33_333, 8)] {
+            let src = data.clone();
+            let scan = ParallelScan::new(
+                move || Ok(Cursor::new(src.clone())),
+                data.len() as u64,
+                chunksize,
+                threads,
+                slice_entropy,
+            )
+            .unwrap();
+            let expected: Vec<(u64, f64)> = data
+                .chunks_exact(chunksize)
+                .enumerate()
+                .map(|(i, c)| ((i * chunksize) as u64, slice_entropy(c)))
+                .collect();
+
+            assert_eq!(scan.map(|r| r.unwrap()).collect::<Vec<_>>(), expected);
+        }
+    }
+
+    #[test]
+    fn early_drop() {
+        let data = data();
+        let src = data.clone();
+        let mut scan = ParallelScan::new(
+            move || Ok(Cursor::new(src.clone())),
+            data.len() as u64,
+            16,
+            4,
+            |c: &[u8]| c.len(),
+        )
+        .unwrap();
+        assert_eq!(scan.next().unwrap().unwrap(), (0, 16));
+    }
+
+    #[test]
+    fn short_input() {
+        let data = data();
+        // claims to be longer than it is, so reading fails
+        let src = data.clone();
+        let mut scan = ParallelScan::new(
+            move || Ok(Cursor::new(src.clone())),
+            data.len() as u64 * 2,
+            1 << 16,
+            2,
+            |c: &[u8]| c.len(),
+        )
+        .unwrap();
+        assert!(matches!(scan.next(), Some(Err(Error::Io(_)))));
+        assert!(scan.next().is_none());
+    }
+
+    #[test]
+    fn zero_length() {
+        let open = || Ok(Cursor::new(vec![0u8; 16]));
+        assert!(matches!(
+            ParallelScan::new(open, 16, 0, 1, slice_entropy),
+            Err(Error::ZeroLength(_))
+        ));
+        assert!(matches!(
+            ParallelScan::new(open, 16, 4, 0, slice_entropy),
+            Err(Error::ZeroLength(_))
+        ));
+    }
+}
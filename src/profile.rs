@@ -0,0 +1,336 @@
+use std::io::{BufRead, ErrorKind, Read, Write};
+
+use crate::Error;
+
+const VERSION: u8 = 1;
+// marks an unknown size
+const UNKNOWN: u64 = u64::MAX;
+
+/// The entropy of every chunk of an input, saved so the map can be drawn again without the input.
+///
+/// Profiles are written by `tropy --save-profile` and drawn by `tropy render`. On disk all
+/// integers are little endian:
+///
+/// | bytes           | content                                                   |
+/// |-----------------|-----------------------------------------------------------|
+/// | 8               | `TROPYPRF`                                                |
+/// | 1               | format version, 1                                         |
+/// | 2 + n           | length and UTF-8 of the source name                       |
+/// | 8               | size of the source in bytes, `u64::MAX` if unknown        |
+/// | 1 + n           | length (0 or 32) and SHA-256 of the source                |
+/// | 8               | chunk size                                                |
+/// | 8               | stride between the starts of two chunks                   |
+/// | 1 + n           | length and UTF-8 of the name of the metric                |
+/// | 8               | number of chunks                                          |
+/// | 4 per chunk     | the values as `f32`                                       |
+///
+/// # Example
+///```
+/// use tropy::Profile;
+///
+/// let mut p = Profile::new("firmware.bin", 1024, "shannon");
+/// p.values = vec![0.0, 7.5, 4.25];
+///
+/// let mut file = Vec::new();
+/// p.write_to(&mut file).unwrap();
+/// assert_eq!(Profile::read_from(&file[..]).unwrap(), p);
+///```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    /// Where the values came from, e.g. the path of the input
+    pub source: String,
+    /// The size of the source in bytes, if known
+    pub size: Option<u64>,
+    /// The SHA-256 of the source, if known
+    pub hash: Option<[u8; 32]>,
+    /// The number of bytes in each chunk
+    pub chunk_size: u64,
+    /// The number of bytes between the starts of two consecutive chunks
+    pub stride: u64,
+    /// What the values are, e.g. `shannon` or `miller-madow`
+    pub metric: String,
+    /// The value of every chunk in bits per byte, in the order of their offsets; saved with the
+    /// precision of an `f32`
+    pub values: Vec<f64>,
+}
+
+impl Profile {
+    /// The first bytes of every profile
+    pub const MAGIC: &'static [u8; 8] = b"TROPYPRF";
+
+    /// An empty profile of disjoint chunks of `chunk_size` bytes.
+    pub fn new(source: &str, chunk_size: u64, metric: &str) -> Self {
+        Profile {
+            source: source.to_string(),
+            size: None,
+            hash: None,
+            chunk_size,
+            stride: chunk_size,
+            metric: metric.to_string(),
+            values: Vec::new(),
+        }
+    }
+
+    /// Write the profile in the format described above.
+    ///
+    /// Fails with [Error::InvalidArgument] if the source or metric name is too long to be saved.
+    pub fn write_to<W: Write>(&self, mut w: W) -> Result<(), Error> {
+        if self.source.len() > u16::MAX as usize || self.metric.len() > u8::MAX as usize {
+            return Err(Error::InvalidArgument(
+                "The source or metric name of the profile is too long".to_string(),
+            ));
+        }
+        w.write_all(Profile::MAGIC)?;
+        w.write_all(&[VERSION])?;
+        w.write_all(&(self.source.len() as u16).to_le_bytes())?;
+        w.write_all(self.source.as_bytes())?;
+        w.write_all(&self.size.unwrap_or(UNKNOWN).to_le_bytes())?;
+        match &self.hash {
+            Some(hash) => {
+                w.write_all(&[hash.len() as u8])?;
+                w.write_all(hash)?;
+            }
+            None => w.write_all(&[0])?,
+        }
+        w.write_all(&self.chunk_size.to_le_bytes())?;
+        w.write_all(&self.stride.to_le_bytes())?;
+        w.write_all(&[self.metric.len() as u8])?;
+        w.write_all(self.metric.as_bytes())?;
+        w.write_all(&(self.values.len() as u64).to_le_bytes())?;
+        for v in self.values.iter() {
+            w.write_all(&(*v as f32).to_le_bytes())?;
+        }
+        w.flush()?;
+        Ok(())
+    }
+
+    /// Read a profile written by [Profile::write_to].
+    ///
+    /// Fails with [Error::UnsupportedFormat] if the input isn't a profile of a known version or is
+    /// cut short, and with [Error::Io] if reading fails.
+    pub fn read_from<R: Read>(mut r: R) -> Result<Self, Error> {
+        let mut magic = [0u8; 8];
+        read(&mut r, &mut magic)?;
+        if &magic != Profile::MAGIC {
+            return Err(Error::UnsupportedFormat(
+                "This is not a tropy profile".to_string(),
+            ));
+        }
+        let version = u8s::<1>(&mut r)?[0];
+        if version != VERSION {
+            return Err(Error::UnsupportedFormat(format!(
+                "Unknown profile version {}",
+                version
+            )));
+        }
+        let len = u16::from_le_bytes(u8s(&mut r)?) as usize;
+        let source = string(&mut r, len)?;
+        let size = Some(u64::from_le_bytes(u8s(&mut r)?)).filter(|s| *s != UNKNOWN);
+        let hash = match u8s::<1>(&mut r)?[0] {
+            0 => None,
+            32 => Some(u8s::<32>(&mut r)?),
+            len => {
+                return Err(Error::UnsupportedFormat(format!(
+                    "Invalid hash length {} in the profile",
+                    len
+                )))
+            }
+        };
+        let chunk_size = u64::from_le_bytes(u8s(&mut r)?);
+        let stride = u64::from_le_bytes(u8s(&mut r)?);
+        if chunk_size == 0 || stride == 0 {
+            return Err(Error::UnsupportedFormat(
+                "The chunk size and stride of the profile must be at least 1".to_string(),
+            ));
+        }
+        let len = u8s::<1>(&mut r)?[0] as usize;
+        let metric = string(&mut r, len)?;
+        let count = u64::from_le_bytes(u8s(&mut r)?);
+        // don't trust the count with the allocation
+        let mut values = Vec::with_capacity(count.min(1 << 20) as usize);
+        for _ in 0..count {
+            values.push(f32::from_le_bytes(u8s(&mut r)?) as f64);
+        }
+        Ok(Profile {
+            source,
+            size,
+            hash,
+            chunk_size,
+            stride,
+            metric,
+            values,
+        })
+    }
+
+    /// Import the output of `tropy --csv`, i.e. lines of `<startbyte>;<entropy>` optionally
+    /// followed by further columns and preceded by a header of quoted column names.
+    ///
+    /// The stride is taken from the distance between the offsets, which must be the same
+    /// throughout. So is the chunk size, unless the header names the window of a sliding window
+    /// scan as `"entropy over <n> bytes"`. Fails with [Error::UnsupportedFormat] if a line can't be parsed, the offsets
+    /// aren't evenly spaced or there are fewer than two lines to tell the distance from.
+    pub fn from_csv<R: BufRead>(r: R, source: &str) -> Result<Self, Error> {
+        let mut offsets = Vec::new();
+        let mut values = Vec::new();
+        let mut window = None;
+        for (i, line) in r.lines().enumerate() {
+            let line = line.map_err(|e| match e.kind() {
+                ErrorKind::InvalidData => {
+                    Error::UnsupportedFormat("The csv isn't text".to_string())
+                }
+                _ => Error::Io(e),
+            })?;
+            let line = line.trim();
+            if line.starts_with('"') {
+                window = line
+                    .split(';')
+                    .nth(1)
+                    .and_then(|h| h.strip_prefix("\"entropy over "))
+                    .and_then(|h| h.strip_suffix(" bytes\""))
+                    .map(|n| {
+                        n.parse::<u64>().ok().filter(|n| *n > 0).ok_or_else(|| {
+                            Error::UnsupportedFormat(format!(
+                                "Invalid window in csv line {}",
+                                i + 1
+                            ))
+                        })
+                    })
+                    .transpose()?;
+                continue;
+            }
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = || Error::UnsupportedFormat(format!("Invalid csv line {}", i + 1));
+            let mut columns = line.split(';');
+            let offset: u64 = columns
+                .next()
+                .and_then(|o| o.parse().ok())
+                .ok_or_else(invalid)?;
+            let value: f64 = columns
+                .next()
+                .and_then(|v| v.parse().ok())
+                .ok_or_else(invalid)?;
+            offsets.push(offset);
+            values.push(value);
+        }
+        if offsets.len() < 2 {
+            return Err(Error::UnsupportedFormat(
+                "The csv needs at least two chunks to tell the chunk size".to_string(),
+            ));
+        }
+        let stride = offsets[1].saturating_sub(offsets[0]);
+        if stride == 0
+            || offsets
+                .windows(2)
+                .any(|w| w[1].checked_sub(w[0]) != Some(stride))
+        {
+            return Err(Error::UnsupportedFormat(
+                "The offsets in the csv aren't evenly spaced".to_string(),
+            ));
+        }
+        let mut profile = Profile::new(source, window.unwrap_or(stride), "csv");
+        profile.stride = stride;
+        profile.values = values;
+        Ok(profile)
+    }
+}
+
+/// Fill `buf`, reporting the end of the input as a truncated profile
+fn read<R: Read>(r: &mut R, buf: &mut [u8]) -> Result<(), Error> {
+    r.read_exact(buf).map_err(|e| match e.kind() {
+        ErrorKind::UnexpectedEof => {
+            Error::UnsupportedFormat("The profile is truncated".to_string())
+        }
+        _ => Error::Io(e),
+    })
+}
+
+fn u8s<const N: usize>(r: &mut impl Read) -> Result<[u8; N], Error> {
+    let mut buf = [0u8; N];
+    read(r, &mut buf)?;
+    Ok(buf)
+}
+
+fn string<R: Read>(r: &mut R, len: usize) -> Result<String, Error> {
+    let mut buf = vec![0u8; len];
+    read(r, &mut buf)?;
+    String::from_utf8(buf)
+        .map_err(|e| Error::UnsupportedFormat(format!("Invalid name in the profile: {}", e)))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, Profile};
+
+    fn profile() -> Profile {
+        let mut p = Profile::new("some/input.bin", 512, "miller-madow");
+        p.size = Some(512 * 3 + 17);
+        p.hash = Some([0xab; 32]);
+        p.stride = 256;
+        p.values = vec![0.0, 7.999, 3.5, 1.0 / 3.0];
+        p
+    }
+
+    #[test]
+    fn round_trip() {
+        let p = profile();
+        let mut file = Vec::new();
+        p.write_to(&mut file).unwrap();
+        let back = Profile::read_from(&file[..]).unwrap();
+        assert_eq!(back.values[1], 7.999f32 as f64);
+        assert_eq!(
+            Profile {
+                values: p.values.iter().map(|v| *v as f32 as f64).collect(),
+                ..p
+            },
+            back
+        );
+
+        let empty = Profile::new("-", 1, "shannon");
+        let mut file = Vec::new();
+        empty.write_to(&mut file).unwrap();
+        assert_eq!(Profile::read_from(&file[..]).unwrap(), empty);
+    }
+
+    #[test]
+    fn invalid() {
+        let mut file = Vec::new();
+        profile().write_to(&mut file).unwrap();
+        for len in [0, 5, 9, 30, file.len() - 1].iter() {
+            assert!(matches!(
+                Profile::read_from(&file[..*len]),
+                Err(Error::UnsupportedFormat(_))
+            ));
+        }
+        let mut version = file.clone();
+        version[8] = 2;
+        assert!(Profile::read_from(&version[..]).is_err());
+        assert!(Profile::read_from(&b"0;7.5\n1024;7.9\n"[..]).is_err());
+    }
+
+    #[test]
+    fn csv() {
+        let csv =
+            "\"start\";\"entropy\";\"class\"\n0;7.5;random\n1024;0.000000;zero\n2048;4.25;code\n";
+        let p = Profile::from_csv(csv.as_bytes(), "scan.csv").unwrap();
+        assert_eq!((p.chunk_size, p.stride), (1024, 1024));
+        assert_eq!(p.values, vec![7.5, 0.0, 4.25]);
+        assert_eq!(p.metric, "csv");
+
+        let csv = "\"start\";\"entropy over 4096 bytes\"\n0;7.5\n512;7.25\n1024;7.0\n";
+        let p = Profile::from_csv(csv.as_bytes(), "window.csv").unwrap();
+        assert_eq!((p.chunk_size, p.stride), (4096, 512));
+        assert!(
+            Profile::from_csv(&b"\"start\";\"entropy over 0 bytes\"\n0;1\n1;1\n"[..], "").is_err()
+        );
+
+        assert!(Profile::from_csv(&b"0;7.5\n1024;7.9\n4096;1.0\n"[..], "").is_err());
+        assert!(Profile::from_csv(&b"0;7.5\n"[..], "").is_err());
+        assert!(Profile::from_csv(&b"0;7.5\n1024;x\n"[..], "").is_err());
+        assert!(matches!(
+            Profile::from_csv(&b"\xff\xfe\n"[..], ""),
+            Err(Error::UnsupportedFormat(_))
+        ));
+    }
+}
@@ -0,0 +1,289 @@
+use std::fs;
+use std::time::UNIX_EPOCH;
+
+use crate::{Class, ElfReport, EntReport, Hit, MarkovReport, Uniformity};
+
+/// The results of scanning an input in chunks, as written by `tropy --format json`.
+///
+/// With the `serde` feature all parts of the report can be serialised and deserialised; fields
+/// which weren't calculated are left out.
+///
+/// # Example
+///```
+/// use tropy::{slice_entropy, ChunkRecord, Input, ScanReport};
+///
+/// let data = [[0u8; 64], [7u8; 64]].concat();
+/// let mut report = ScanReport::new(Input::of("-"), 32, 32);
+/// for (i, chunk) in data.chunks(32).enumerate() {
+///     report.push(ChunkRecord::new(i as u64 * 32, 32, slice_entropy(chunk)));
+/// }
+/// assert_eq!(report.chunks.len(), 4);
+/// assert_eq!(report.summary.bytes, 128);
+/// assert_eq!(report.summary.max, 0.0);
+///```
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScanReport {
+    /// What was scanned
+    pub input: Input,
+    /// The number of bytes in each chunk
+    pub chunk_size: u64,
+    /// The number of bytes between the starts of two consecutive chunks
+    pub stride: u64,
+    /// The chunks in the order of their offsets
+    pub chunks: Vec<ChunkRecord>,
+    /// Statistics over all chunks
+    pub summary: Summary,
+    /// The entropy of the sections and segments, if the input was read as an ELF file
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub elf: Option<ElfReport>,
+}
+
+/// The input of a scan.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Input {
+    /// The path as given, `-` for stdin
+    pub path: String,
+    /// The size in bytes, if the input is a file
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub size: Option<u64>,
+    /// The time of the last modification in seconds since the unix epoch, if known
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub modified: Option<u64>,
+}
+
+/// The values calculated for one chunk.
+///
+/// Only the offset, length and entropy are always present; the rest depend on what was asked for.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChunkRecord {
+    /// Offset of the first byte
+    pub offset: u64,
+    /// The number of bytes
+    pub len: u64,
+    /// The selected entropy measure in bits per byte
+    pub entropy: f64,
+    /// The tests of ent, see [EntReport]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub ent: Option<EntReport>,
+    /// The conditional entropy, see [MarkovReport]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub markov: Option<MarkovReport>,
+    /// Whether the chunk looks random by its histogram but is predictable from its context, see
+    /// [MarkovReport::is_structured]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub structured: Option<bool>,
+    /// The test against random bytes, see [Uniformity]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub uniformity: Option<Uniformity>,
+    /// The LZ77 compressed size in bits per byte
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub lz: Option<f64>,
+    /// The Jensen-Shannon divergence from the reference
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub divergence: Option<f64>,
+    /// The kind of data, see [Class]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub class: Option<Class>,
+    /// The signatures of embedded files starting in the chunk, see [crate::SignatureScanner]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub signatures: Option<Vec<Hit>>,
+    /// The ELF section the first byte belongs to, see [crate::Elf::section_at]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub section: Option<String>,
+    /// The loadable ELF segment the first byte belongs to, see [crate::Elf::segment_at]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub segment: Option<String>,
+}
+
+/// Statistics over the chunks of a scan.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Summary {
+    /// The number of chunks
+    pub chunks: u64,
+    /// The number of bytes in all chunks, counting overlapping bytes once per chunk
+    pub bytes: u64,
+    /// The lowest entropy of a chunk, 0 if there were none
+    pub min: f64,
+    /// The highest entropy of a chunk, 0 if there were none
+    pub max: f64,
+    /// The mean entropy of the chunks, 0 if there were none
+    pub mean: f64,
+    /// The tests of ent over the whole input, if they were run
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub ent: Option<EntReport>,
+    /// The signatures no chunk was recorded for, e.g. all of them in an input shorter than a
+    /// chunk
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub signatures: Vec<Hit>,
+}
+
+impl ScanReport {
+    /// An empty report for an input scanned in chunks of `chunk_size` bytes starting every
+    /// `stride` bytes.
+    pub fn new(input: Input, chunk_size: u64, stride: u64) -> Self {
+        ScanReport {
+            input,
+            chunk_size,
+            stride,
+            chunks: Vec::new(),
+            summary: Summary::default(),
+            elf: None,
+        }
+    }
+
+    /// Append the next chunk and add it to the summary.
+    pub fn push(&mut self, record: ChunkRecord) {
+        self.summary.add(&record);
+        self.chunks.push(record);
+    }
+}
+
+impl Input {
+    /// Describe a path, reading the metadata of the file if there is one.
+    ///
+    /// `-` stands for stdin and has neither a size nor a modification time.
+    pub fn of(path: &str) -> Self {
+        let metadata = if path == "-" {
+            None
+        } else {
+            fs::metadata(path).ok()
+        };
+        Input {
+            path: path.to_string(),
+            size: metadata.as_ref().filter(|m| m.is_file()).map(|m| m.len()),
+            modified: metadata
+                .and_then(|m| m.modified().ok())
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs()),
+        }
+    }
+}
+
+impl ChunkRecord {
+    /// A record with only the entropy.
+    pub fn new(offset: u64, len: u64, entropy: f64) -> Self {
+        ChunkRecord {
+            offset,
+            len,
+            entropy,
+            ..ChunkRecord::default()
+        }
+    }
+}
+
+impl Summary {
+    /// Count a chunk in.
+    pub fn add(&mut self, record: &ChunkRecord) {
+        let e = record.entropy;
+        if self.chunks == 0 {
+            self.min = e;
+            self.max = e;
+        } else {
+            self.min = self.min.min(e);
+            self.max = self.max.max(e);
+        }
+        self.chunks += 1;
+        self.bytes += record.len;
+        self.mean += (e - self.mean) / self.chunks as f64;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{ChunkRecord, Input, ScanReport, Summary};
+
+    #[test]
+    fn summary() {
+        let mut s = Summary::default();
+        assert_eq!((s.min, s.max, s.mean), (0.0, 0.0, 0.0));
+        for (i, e) in [4.0, 8.0, 3.0, 5.0].iter().enumerate() {
+            s.add(&ChunkRecord::new(i as u64 * 10, 10, *e));
+        }
+        assert_eq!((s.chunks, s.bytes), (4, 40));
+        assert_eq!((s.min, s.max, s.mean), (3.0, 8.0, 5.0));
+    }
+
+    #[test]
+    fn input() {
+        let stdin = Input::of("-");
+        assert_eq!((stdin.size, stdin.modified), (None, None));
+        let manifest = Input::of(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"));
+        assert!(manifest.size.unwrap() > 0);
+        assert!(manifest.modified.is_some());
+        assert_eq!(Input::of("/does/not/exist").size, None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json() {
+        use crate::{Class, EntReport};
+
+        let mut report = ScanReport::new(Input::of("-"), 16, 8);
+        report.push(ChunkRecord::new(0, 16, 1.5));
+        report.push(ChunkRecord {
+            class: Some(Class::Text),
+            ent: Some(EntReport::of(b"0123456789abcdef")),
+            ..ChunkRecord::new(8, 16, 4.0)
+        });
+        let json = serde_json::to_string(&report).unwrap();
+        // unset fields are left out
+        assert!(
+            json.contains(r#"{"offset":0,"len":16,"entropy":1.5}"#),
+            "{}",
+            json
+        );
+        assert!(json.contains(r#""class":"text""#), "{}", json);
+        assert!(!json.contains("modified"), "{}", json);
+        let back: ScanReport = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, report);
+    }
+}
@@ -0,0 +1,221 @@
+use std::io;
+use std::io::Write;
+
+use crate::{Error, Histogram};
+
+/// A sliding-window entropy calculator.
+///
+/// Keeps the last `window` bytes in a ring buffer. Every new byte evicts the oldest one and the
+/// entropy is updated incrementally, so both [RollingCalculator::push] and
+/// [RollingCalculator::entropy] are O(1) regardless of the window size.
+///
+/// # Example
+///```
+/// use tropy::RollingCalculator;
+///
+/// let mut c = RollingCalculator::new(2).unwrap();
+/// c.push(0u8);
+/// c.push(1u8);
+/// assert_eq!(c.entropy(), 1.0);
+///
+/// // the window is full, so this drops the leading 0
+/// c.push(1u8);
+/// assert_eq!(c.entropy(), 0.0);
+///```
+pub struct RollingCalculator {
+    ring: Box<[u8]>,
+    // index of the oldest byte once the window is full, otherwise the next free slot
+    head: usize,
+    len: usize,
+    counts: Box<[u64; 256]>,
+    // running sum of c * log2(c) over all counts
+    sum: f64,
+    // number of updates since `sum` was last recomputed from scratch
+    drift: usize,
+}
+
+impl RollingCalculator {
+    /// Instantiate a new calculator over a window of `window` bytes.
+    ///
+    /// Fails with [Error::ZeroLength] if `window` is zero.
+    pub fn new(window: usize) -> Result<Self, Error> {
+        if window == 0 {
+            return Err(Error::ZeroLength("window"));
+        }
+        Ok(RollingCalculator {
+            ring: vec![0u8; window].into_boxed_slice(),
+            head: 0,
+            len: 0,
+            counts: Box::new([0u64; 256]),
+            sum: 0.0,
+            drift: 0,
+        })
+    }
+
+    /// The size of the window in bytes.
+    pub fn window(&self) -> usize {
+        self.ring.len()
+    }
+
+    /// The number of bytes currently held in the window.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether no bytes have been pushed yet.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether the window has been filled completely.
+    pub fn is_full(&self) -> bool {
+        self.len == self.ring.len()
+    }
+
+    /// Add a byte to the window. If the window was already full the oldest byte is dropped and
+    /// returned.
+    pub fn push(&mut self, byte: u8) -> Option<u8> {
+        let evicted = if self.is_full() {
+            let old = self.ring[self.head];
+            self.ring[self.head] = byte;
+            self.head = (self.head + 1) % self.ring.len();
+            self.decrement(old);
+            Some(old)
+        } else {
+            let slot = (self.head + self.len) % self.ring.len();
+            self.ring[slot] = byte;
+            self.len += 1;
+            None
+        };
+        self.increment(byte);
+
+        // floating point errors accumulate in the running sum, so rebuild it once per window
+        // which keeps the amortised cost per byte constant
+        self.drift += 1;
+        if self.drift >= self.ring.len() {
+            self.resync();
+        }
+
+        evicted
+    }
+
+    /// Calculate the Shannon entropy of the bytes currently in the window.
+    ///
+    /// The window is left as it is, so this can be called after every [RollingCalculator::push].
+    pub fn entropy(&self) -> f64 {
+        if self.len == 0 {
+            return 0.0;
+        }
+        let n = self.len as f64;
+        // H = -sum(c/n * log2(c/n)) = log2(n) - sum(c * log2(c)) / n
+        let e = n.log2() - self.sum / n;
+        // a single repeated byte must be exactly zero, not -1e-16
+        if e < 0.0 {
+            0.0
+        } else {
+            e
+        }
+    }
+
+    /// Copy the histogram of the bytes currently in the window, e.g. to calculate one of the
+    /// other [crate::Metric]s over it.
+    pub fn histogram(&self) -> Histogram {
+        Histogram::from_counts(&self.counts)
+    }
+
+    /// Drop all bytes from the window.
+    pub fn clear(&mut self) {
+        self.counts.iter_mut().for_each(|c| *c = 0u64);
+        self.head = 0;
+        self.len = 0;
+        self.sum = 0.0;
+        self.drift = 0;
+    }
+
+    fn increment(&mut self, byte: u8) {
+        let c = &mut self.counts[byte as usize];
+        self.sum += xlog2x(*c + 1) - xlog2x(*c);
+        *c += 1;
+    }
+
+    fn decrement(&mut self, byte: u8) {
+        let c = &mut self.counts[byte as usize];
+        self.sum += xlog2x(*c - 1) - xlog2x(*c);
+        *c -= 1;
+    }
+
+    fn resync(&mut self) {
+        self.sum = self.counts.iter().cloned().map(xlog2x).sum();
+        self.drift = 0;
+    }
+}
+
+impl Write for RollingCalculator {
+    fn write(&mut self, input: &[u8]) -> io::Result<usize> {
+        input.iter().for_each(|byte| {
+            self.push(*byte);
+        });
+        Ok(input.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn xlog2x(c: u64) -> f64 {
+    if c == 0 {
+        0.0
+    } else {
+        let c = c as f64;
+        c * c.log2()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_util::noise;
+    use crate::{slice_entropy, Error, Histogram, RollingCalculator};
+
+    #[test]
+    fn matches_slice_entropy() {
+        // noise with a bit of structure mixed in
+        let data: Vec<u8> = noise(4096)
+            .iter()
+            .enumerate()
+            .map(|(i, b)| if i % 3 == 0 { 7 } else { *b })
+            .collect();
+        let window = 100;
+        let mut c = RollingCalculator::new(window).unwrap();
+
+        for (i, byte) in data.iter().enumerate() {
+            c.push(*byte);
+            let start = (i + 1).saturating_sub(window);
+            let expected = slice_entropy(&data[start..=i]);
+            assert!((c.entropy() - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn eviction() {
+        assert!(matches!(
+            RollingCalculator::new(0),
+            Err(Error::ZeroLength(_))
+        ));
+        let mut c = RollingCalculator::new(3).unwrap();
+        assert!(c.is_empty());
+        assert_eq!(c.push(1), None);
+        assert_eq!(c.push(2), None);
+        assert_eq!(c.push(3), None);
+        assert!(c.is_full());
+        assert_eq!(c.push(4), Some(1));
+        assert_eq!(c.push(5), Some(2));
+        assert_eq!(c.len(), 3);
+
+        assert_eq!(c.histogram(), Histogram::from(&[3u8, 4, 5][..]));
+
+        c.clear();
+        assert!(c.is_empty());
+        assert_eq!(c.entropy(), 0.0);
+    }
+}
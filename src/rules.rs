@@ -0,0 +1,363 @@
+use std::str::FromStr;
+
+use crate::{Error, Histogram};
+
+/// The most bytes a rule may look at from the start of its hit.
+///
+/// A [crate::SignatureScanner] holds back the last [Rule::window] bytes of the widest rule it
+/// was given and copies them along with every update, so a rule like `entropy>=7.5/1024k` makes
+/// scanning with small pieces slow and keeps up to 1 MiB of input in memory. Callers which hold
+/// their own data until [crate::SignatureScanner::settled] passes it keep that much as well.
+pub const MAX_RULE_WINDOW: usize = 1 << 20;
+
+/// A user-defined signature: a byte pattern with wildcards and masks, optionally at a fixed
+/// offset and followed by bytes of a certain entropy.
+///
+/// Rules are read from text by [RuleSet::from_str] and found by a
+/// [SignatureScanner](crate::SignatureScanner) created with
+/// [with_rules](crate::SignatureScanner::with_rules).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    /// The name reported in the [Hit](crate::Hit)s
+    pub name: String,
+    // (value, mask) of every byte of the pattern
+    pattern: Vec<(u8, u8)>,
+    // from the start of the hit to the pattern
+    offset: usize,
+    // the only offset in the input a hit may start at
+    at: Option<u64>,
+    conditions: Vec<Condition>,
+}
+
+/// A bound on the entropy of the bytes after the pattern.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Condition {
+    // whether the entropy must be at least or at most `bits`
+    at_least: bool,
+    bits: f64,
+    span: usize,
+}
+
+/// The rules of a rule file.
+///
+/// Every line holds one rule: its name, the bytes of the pattern and options, separated by
+/// whitespace. Everything after a `#` is ignored.
+///
+/// | token              | meaning                                                                |
+/// |--------------------|------------------------------------------------------------------------|
+/// | `4d`               | a byte in hex                                                          |
+/// | `??`               | any byte                                                               |
+/// | `4?`, `?d`         | a byte with one nibble given                                           |
+/// | `40&f0`            | a byte which equals `40` after masking with `f0`                       |
+/// | `offset=16`        | the pattern starts 16 bytes after the reported start of the header     |
+/// | `at=0x200`         | hits must start at this offset of the input                            |
+/// | `entropy>=7.5/4k`  | the 4 KiB after the pattern have at least 7.5 bits per byte            |
+/// | `entropy<=1/512`   | the 512 bytes after the pattern have at most 1 bit per byte            |
+///
+/// Numbers are decimal or hex with `0x`, sizes may end in `k` for KiB. Entropy conditions at the
+/// end of the input are evaluated over the bytes which are left. A rule may look at no more than
+/// [MAX_RULE_WINDOW] bytes from the start of its hit.
+///
+/// # Example
+///```
+/// use tropy::{RuleSet, SignatureScanner};
+///
+/// // a proprietary container: magic, two bytes of version, type 1 in the low nibble
+/// let rules: RuleSet = "acme-fw  41 43 4d 45 ?? ?? ?1  entropy>=7/256  # compressed payload"
+///     .parse()
+///     .unwrap();
+///
+/// let mut data = vec![0u8; 1024];
+/// data[100..107].copy_from_slice(b"ACME\x02\x00\x31");
+/// // a counter as payload, 8 bits per byte
+/// for (i, b) in data[107..363].iter_mut().enumerate() {
+///     *b = i as u8;
+/// }
+/// data[600..607].copy_from_slice(b"ACME\x02\x00\x31");
+///
+/// let mut s = SignatureScanner::with_rules(rules);
+/// let mut hits = s.update(&data);
+/// hits.extend(s.finish());
+/// // the second header is followed by zeros
+/// assert_eq!(hits.len(), 1);
+/// assert_eq!(hits[0].to_string(), "acme-fw@0x64");
+///```
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RuleSet {
+    rules: Vec<Rule>,
+}
+
+impl Rule {
+    /// The number of bytes from the start of a hit the rule looks at.
+    pub fn window(&self) -> usize {
+        let span = self.conditions.iter().map(|c| c.span).max().unwrap_or(0);
+        self.offset
+            .saturating_add(self.pattern.len())
+            .saturating_add(span)
+    }
+
+    /// Whether a hit starts with `data`, found at `offset` of the input.
+    ///
+    /// `data` should hold the [Rule::window] bytes from the start on, or what is left of the input.
+    pub fn matches(&self, data: &[u8], offset: u64) -> bool {
+        if self.at.is_some_and(|at| at != offset) {
+            return false;
+        }
+        let end = self.offset + self.pattern.len();
+        if data.len() < end
+            || !data[self.offset..end]
+                .iter()
+                .zip(self.pattern.iter())
+                .all(|(b, (value, mask))| b & mask == *value)
+        {
+            return false;
+        }
+        self.conditions.iter().all(|c| {
+            let after = &data[end..(end + c.span).min(data.len())];
+            if after.is_empty() {
+                return false;
+            }
+            let entropy = Histogram::from(after).entropy();
+            if c.at_least {
+                entropy >= c.bits
+            } else {
+                entropy <= c.bits
+            }
+        })
+    }
+}
+
+impl RuleSet {
+    /// The rules in the order of the file.
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// The number of rules.
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// Whether there are no rules.
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+impl FromStr for RuleSet {
+    type Err = Error;
+
+    /// Parses a rule file as described above.
+    ///
+    /// Fails with [Error::UnsupportedFormat] naming the line of the first invalid rule.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rules = Vec::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            if line.trim().is_empty() {
+                continue;
+            }
+            let rule = parse_rule(line).map_err(|why| {
+                Error::UnsupportedFormat(format!("Invalid rule on line {}: {}", i + 1, why))
+            })?;
+            rules.push(rule);
+        }
+        Ok(RuleSet { rules })
+    }
+}
+
+fn parse_rule(line: &str) -> Result<Rule, String> {
+    let mut tokens = line.split_whitespace();
+    let name = tokens.next().unwrap_or("").to_string();
+    let mut rule = Rule {
+        name,
+        pattern: Vec::new(),
+        offset: 0,
+        at: None,
+        conditions: Vec::new(),
+    };
+    for token in tokens {
+        if let Some(value) = token.strip_prefix("offset=") {
+            rule.offset = size(value)?;
+        } else if let Some(value) = token.strip_prefix("at=") {
+            rule.at = Some(size(value)? as u64);
+        } else if let Some(value) = token.strip_prefix("entropy>=") {
+            rule.conditions.push(condition(value, true)?);
+        } else if let Some(value) = token.strip_prefix("entropy<=") {
+            rule.conditions.push(condition(value, false)?);
+        } else if token.contains('=') {
+            return Err(format!("unknown option {:?}", token));
+        } else {
+            rule.pattern.push(byte(token)?);
+        }
+    }
+    if rule.pattern.iter().all(|(_, mask)| *mask == 0) {
+        return Err("the pattern needs at least one byte which isn't a wildcard".to_string());
+    }
+    if rule.window() > MAX_RULE_WINDOW {
+        return Err(format!(
+            "the rule looks at more than {} bytes",
+            MAX_RULE_WINDOW
+        ));
+    }
+    Ok(rule)
+}
+
+/// A byte of the pattern as (value, mask)
+fn byte(token: &str) -> Result<(u8, u8), String> {
+    let invalid = || format!("invalid pattern byte {:?}", token);
+    if let Some((value, mask)) = token.split_once('&') {
+        let value = u8::from_str_radix(value, 16).map_err(|_| invalid())?;
+        let mask = u8::from_str_radix(mask, 16).map_err(|_| invalid())?;
+        if value & !mask != 0 {
+            return Err(format!("{:?} has bits outside of its mask", token));
+        }
+        return Ok((value, mask));
+    }
+    let nibbles: Vec<char> = token.chars().collect();
+    if nibbles.len() != 2 {
+        return Err(invalid());
+    }
+    let mut value = 0;
+    let mut mask = 0;
+    for c in nibbles {
+        value <<= 4;
+        mask <<= 4;
+        if c != '?' {
+            value |= c.to_digit(16).ok_or_else(invalid)? as u8;
+            mask |= 0xf;
+        }
+    }
+    Ok((value, mask))
+}
+
+/// A number of bytes, decimal or hex with `0x`, optionally in KiB
+fn size(token: &str) -> Result<usize, String> {
+    let invalid = || format!("invalid size {:?}", token);
+    let (digits, unit) = match token.strip_suffix(['k', 'K']) {
+        Some(digits) => (digits, 1024),
+        None => (token, 1),
+    };
+    let n = match digits.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => digits.parse(),
+    }
+    .map_err(|_| invalid())?;
+    n.checked_mul(unit).ok_or_else(invalid)
+}
+
+/// `<bits>/<size>` of an entropy condition
+fn condition(token: &str, at_least: bool) -> Result<Condition, String> {
+    let invalid = || format!("invalid entropy condition {:?}, e.g. 7.5/4k", token);
+    let (bits, span) = token.split_once('/').ok_or_else(invalid)?;
+    let bits: f64 = bits.parse().map_err(|_| invalid())?;
+    if !(0.0..=8.0).contains(&bits) {
+        return Err(format!("{} isn't between 0 and 8 bits", bits));
+    }
+    let span = size(span)?;
+    if span == 0 {
+        return Err(invalid());
+    }
+    Ok(Condition {
+        at_least,
+        bits,
+        span,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Error, RuleSet, SignatureScanner};
+
+    fn hits(rules: &str, data: &[u8]) -> Vec<String> {
+        let mut s = SignatureScanner::with_rules(rules.parse().unwrap());
+        let mut hits = Vec::new();
+        // in pieces smaller than the windows of the rules
+        for piece in data.chunks(100) {
+            hits.extend(s.update(piece));
+        }
+        hits.extend(s.finish());
+        hits.iter()
+            .filter(|h| h.name.starts_with("my"))
+            .map(|h| h.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn parse() {
+        let rules: RuleSet = "\n# only a comment\nmy-a 01 ?2 3? ?? 50&f0 offset=0x10 at=4k entropy<=1/2k # trailing\nmy-b ff\n"
+            .parse()
+            .unwrap();
+        assert_eq!(rules.len(), 2);
+        let a = &rules.rules()[0];
+        assert_eq!(a.name, "my-a");
+        assert_eq!(
+            a.pattern,
+            vec![(1, 0xff), (2, 0x0f), (0x30, 0xf0), (0, 0), (0x50, 0xf0)]
+        );
+        assert_eq!((a.offset, a.at), (16, Some(4096)));
+        assert_eq!(a.window(), 16 + 5 + 2048);
+        assert!("".parse::<RuleSet>().unwrap().is_empty());
+
+        for invalid in [
+            "my ?? ??",
+            "my",
+            "my 0g",
+            "my 123",
+            "my 0f&0e",
+            "my 01 entropy>=9/10",
+            "my 01 entropy>=7/0",
+            "my 01 entropy>7/10",
+            "my 01 entropy>=7",
+            "my 01 offset=-1",
+            "my 01 entropy>=7/2048k",
+        ]
+        .iter()
+        {
+            let text = format!("ok 01\n{}\n", invalid);
+            match text.parse::<RuleSet>() {
+                Err(Error::UnsupportedFormat(msg)) => assert!(msg.contains("line 2"), "{}", msg),
+                other => panic!("{:?} for {:?}", other, invalid),
+            }
+        }
+    }
+
+    #[test]
+    fn patterns() {
+        let mut data = vec![0u8; 1000];
+        data[10..14].copy_from_slice(&[0xca, 0xfe, 0x12, 0x34]);
+        data[500..504].copy_from_slice(&[0xca, 0xfe, 0x56, 0x78]);
+        assert_eq!(hits("my ca fe ?? 3?", &data), vec!["my@0xa".to_string()]);
+        assert_eq!(
+            hits("my ca fe 02&03 ??", &data),
+            vec!["my@0xa".to_string(), "my@0x1f4".to_string()]
+        );
+        // the header starts 8 bytes before the pattern
+        assert_eq!(
+            hits("my ca fe offset=8", &data),
+            vec!["my@0x2".to_string(), "my@0x1ec".to_string()]
+        );
+        assert_eq!(hits("my ca fe offset=11", &data), vec!["my@0x1e9"]);
+        assert_eq!(hits("my ca fe at=500", &data), vec!["my@0x1f4"]);
+        // cut off by the end of the input
+        assert!(hits("my ca fe 56 78 9a", &data[..504]).is_empty());
+    }
+
+    #[test]
+    fn entropy() {
+        let mut data = vec![0u8; 3000];
+        data[0..2].copy_from_slice(&[0xab, 0xcd]);
+        for (i, b) in data[2..514].iter_mut().enumerate() {
+            *b = (i * 7) as u8;
+        }
+        data[2000..2002].copy_from_slice(&[0xab, 0xcd]);
+        assert_eq!(hits("my ab cd entropy>=7.9/512", &data), vec!["my@0x0"]);
+        assert_eq!(hits("my ab cd entropy<=0.5/512", &data), vec!["my@0x7d0"]);
+        // both bounds, and only the bytes left at the end
+        assert_eq!(
+            hits("my ab cd entropy>=7.9/256 entropy<=8/1k", &data),
+            vec!["my@0x0"]
+        );
+        assert_eq!(hits("my ab cd entropy<=0/2k", &data), vec!["my@0x7d0"]);
+    }
+}
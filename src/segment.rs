@@ -0,0 +1,167 @@
+use crate::Error;
+
+/// A run of consecutive chunks with a similar entropy, as found by [segment].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Region {
+    /// Offset of the first byte
+    pub start: u64,
+    /// Offset after the last byte
+    pub end: u64,
+    /// Mean entropy of the chunks in the region
+    pub mean: f64,
+}
+
+impl Region {
+    /// The number of bytes covered.
+    pub fn len(&self) -> u64 {
+        self.end - self.start
+    }
+
+    /// Whether the region covers no bytes.
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+/// Split a series of per-chunk entropies into regions of similar entropy.
+///
+/// Value `i` is taken to cover the bytes `i * chunksize` up to `(i + 1) * chunksize`.
+/// Change-points are placed where the mean of the series shifts, using the exact PELT method
+/// (pruned exact linear time, Killick et al. 2012) on the squared error around the region means.
+///
+/// `penalty` is the sensitivity: a change-point is only placed if it reduces the squared error
+/// by more than `penalty` bits². A single chunk which differs from its surroundings by `d` bits
+/// reduces it by about `d²`, so the default of [DEFAULT_PENALTY] keeps chunks deviating by
+/// less than 2 bits in their region. Smaller values give more, shorter regions.
+///
+/// # Example
+///```
+/// use tropy::{segment, DEFAULT_PENALTY};
+///
+/// // a header, a compressed payload and padding
+/// let entropies = [4.1, 4.3, 7.9, 7.95, 7.9, 7.98, 0.0, 0.0];
+/// let regions = segment(&entropies, 1024, DEFAULT_PENALTY).unwrap();
+///
+/// assert_eq!(regions.len(), 3);
+/// assert_eq!((regions[1].start, regions[1].end), (2048, 6144));
+/// assert!((regions[1].mean - 7.9325).abs() < 1e-9);
+///```
+///
+/// Fails with [Error::InvalidArgument] if `penalty` is negative or NaN.
+pub fn segment(entropies: &[f64], chunksize: u64, penalty: f64) -> Result<Vec<Region>, Error> {
+    if penalty.is_nan() || penalty < 0.0 {
+        return Err(Error::InvalidArgument(format!(
+            "The penalty must not be negative: {}",
+            penalty
+        )));
+    }
+    let n = entropies.len();
+
+    // prefix sums of the values and their squares give the cost of any segment in O(1)
+    let mut sum = vec![0.0; n + 1];
+    let mut squares = vec![0.0; n + 1];
+    for (i, e) in entropies.iter().enumerate() {
+        sum[i + 1] = sum[i] + e;
+        squares[i + 1] = squares[i] + e * e;
+    }
+    let cost = |s: usize, t: usize| {
+        let s1 = sum[t] - sum[s];
+        let cost = squares[t] - squares[s] - s1 * s1 / (t - s) as f64;
+        // rounding may push a constant segment slightly below zero
+        cost.max(0.0)
+    };
+
+    // best[t] is the minimal cost of the first t values, last[t] the start of their last segment
+    let mut best = vec![0.0; n + 1];
+    let mut last = vec![0usize; n + 1];
+    best[0] = -penalty;
+    let mut candidates = vec![0usize];
+    for t in 1..=n {
+        let (start, cost_t) = candidates
+            .iter()
+            .map(|&s| (s, best[s] + cost(s, t) + penalty))
+            .fold((0, f64::INFINITY), |a, b| if b.1 < a.1 { b } else { a });
+        best[t] = cost_t;
+        last[t] = start;
+        // a start which is already worse than t can never become the best one again
+        candidates.retain(|&s| best[s] + cost(s, t) <= best[t]);
+        candidates.push(t);
+    }
+
+    let mut regions = Vec::new();
+    let mut t = n;
+    while t > 0 {
+        let s = last[t];
+        regions.push(Region {
+            start: s as u64 * chunksize,
+            end: t as u64 * chunksize,
+            mean: (sum[t] - sum[s]) / (t - s) as f64,
+        });
+        t = s;
+    }
+    regions.reverse();
+    Ok(regions)
+}
+
+/// The penalty used by the binary, see [segment].
+pub const DEFAULT_PENALTY: f64 = 4.0;
+
+#[cfg(test)]
+mod test {
+    use crate::test_util::{xorshift, SEED};
+    use crate::{segment, Error, DEFAULT_PENALTY};
+
+    fn noisy(means: &[(f64, usize)]) -> Vec<f64> {
+        means
+            .iter()
+            .flat_map(|&(mean, n)| std::iter::repeat_n(mean, n))
+            .zip(xorshift(SEED))
+            // +-0.25 bits
+            .map(|(mean, x)| mean + ((x >> 11) as f64 / (1u64 << 53) as f64 - 0.5) / 2.0)
+            .collect()
+    }
+
+    #[test]
+    fn steps() {
+        let series = noisy(&[(5.0, 40), (7.9, 100), (0.5, 3), (6.0, 57)]);
+        let regions = segment(&series, 512, DEFAULT_PENALTY).unwrap();
+        let bounds: Vec<(u64, u64)> = regions.iter().map(|r| (r.start, r.end)).collect();
+        assert_eq!(
+            bounds,
+            vec![
+                (0, 40 * 512),
+                (40 * 512, 140 * 512),
+                (140 * 512, 143 * 512),
+                (143 * 512, 200 * 512)
+            ]
+        );
+        assert!((regions[1].mean - 7.9).abs() < 0.1);
+        assert_eq!(regions[2].len(), 3 * 512);
+    }
+
+    #[test]
+    fn sensitivity() {
+        let series = noisy(&[(5.0, 50), (5.2, 50)]);
+        assert_eq!(segment(&series, 1, DEFAULT_PENALTY).unwrap().len(), 1);
+        assert_eq!(segment(&series, 1, 0.5).unwrap().len(), 2);
+        // every chunk on its own
+        assert_eq!(segment(&series, 1, 0.0).unwrap().len(), 100);
+    }
+
+    #[test]
+    fn degenerate() {
+        assert!(segment(&[], 1024, DEFAULT_PENALTY).unwrap().is_empty());
+        let regions = segment(&[3.0; 10], 16, DEFAULT_PENALTY).unwrap();
+        assert_eq!(regions.len(), 1);
+        assert_eq!(
+            (regions[0].start, regions[0].end, regions[0].mean),
+            (0, 160, 3.0)
+        );
+        assert!(matches!(
+            segment(&[1.0], 1, -1.0),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(segment(&[1.0], 1, f64::NAN).is_err());
+    }
+}
@@ -0,0 +1,173 @@
+#[cfg(feature = "std")]
+use std::io;
+
+#[cfg(any(feature = "std", feature = "libm"))]
+use crate::metric;
+use crate::Error;
+#[cfg(any(feature = "std", feature = "libm"))]
+use crate::Metric;
+
+/// An entropy calculator which doesn't allocate, for targets without a heap.
+///
+/// The counts live inline (1kB), so the calculator can be put on the stack or, since
+/// [StackCalculator::new] is `const`, into a `static`. It is available without the `std` feature;
+/// with it, bytes can also be written through [std::io::Write].
+///
+/// # Example
+///```
+/// use tropy::{StackCalculator, FIXED_ONE};
+///
+/// let mut c = StackCalculator::new();
+/// c.update(&[0u8, 1u8]).unwrap();
+/// assert_eq!(c.entropy_fixed(), FIXED_ONE);
+///
+/// // the state is kept until cleared
+/// c.update(&[2u8, 3u8]).unwrap();
+/// assert_eq!(c.entropy_fixed(), 2 * FIXED_ONE);
+/// c.clear();
+/// assert_eq!(c.total(), 0);
+///```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackCalculator {
+    // u32 halves the size compared to Calculator, which is plenty for firmware sized inputs
+    counts: [u32; 256],
+    total: u64,
+}
+
+impl StackCalculator {
+    /// Instantiate a new calculator.
+    pub const fn new() -> Self {
+        StackCalculator {
+            counts: [0u32; 256],
+            total: 0,
+        }
+    }
+
+    /// Count the given bytes.
+    ///
+    /// Fails with [Error::Overflow] without counting anything if a byte value would be counted
+    /// more than `u32::MAX` times; call [StackCalculator::clear] between chunks.
+    pub fn update(&mut self, input: &[u8]) -> Result<(), Error> {
+        let total = self
+            .total
+            .checked_add(input.len() as u64)
+            .ok_or(Error::Overflow)?;
+        let max = self.counts.iter().max().cloned().unwrap_or(0) as u64;
+        if max + input.len() as u64 > u32::MAX as u64 {
+            // some count may overflow, find out which before touching any
+            let mut added = [0u64; 256];
+            input.iter().for_each(|byte| added[*byte as usize] += 1);
+            if self
+                .counts
+                .iter()
+                .zip(added.iter())
+                .any(|(c, a)| *c as u64 + a > u32::MAX as u64)
+            {
+                return Err(Error::Overflow);
+            }
+        }
+        input
+            .iter()
+            .for_each(|byte| self.counts[*byte as usize] += 1);
+        self.total = total;
+        Ok(())
+    }
+
+    /// The counts of all byte values, indexed by the byte.
+    pub fn counts(&self) -> &[u32; 256] {
+        &self.counts
+    }
+
+    /// The number of bytes counted.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Calculate Shannon entropy over the bytes counted so far.
+    #[cfg(any(feature = "std", feature = "libm"))]
+    pub fn entropy(&self) -> f64 {
+        metric::shannon(self.counts.iter().map(|c| *c as u64), self.total)
+    }
+
+    /// Calculate the given metric over the bytes counted so far.
+    #[cfg(any(feature = "std", feature = "libm"))]
+    pub fn measure(&self, metric: Metric) -> f64 {
+        metric.of_counts(self.counts.iter().map(|c| *c as u64), self.total)
+    }
+
+    /// Calculate Shannon entropy over the bytes counted so far without floating point arithmetic,
+    /// see [crate::fixed_entropy].
+    pub fn entropy_fixed(&self) -> u16 {
+        crate::fixed_entropy(&self.counts)
+    }
+
+    /// Reset all counts to zero.
+    pub fn clear(&mut self) {
+        self.counts = [0u32; 256];
+        self.total = 0;
+    }
+}
+
+impl Default for StackCalculator {
+    fn default() -> Self {
+        StackCalculator::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl io::Write for StackCalculator {
+    fn write(&mut self, input: &[u8]) -> io::Result<usize> {
+        self.update(input)?;
+        Ok(input.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(all(test, any(feature = "std", feature = "libm")))]
+mod test {
+    use crate::{Error, Metric, StackCalculator};
+
+    // usable as static storage
+    static mut CALCULATOR: StackCalculator = StackCalculator::new();
+
+    #[test]
+    fn same_as_calculator() {
+        let data = [0u8, 0, 1, 2, 3, 3, 3, 3, 9, 200, 201, 255];
+        let mut c = StackCalculator::new();
+        c.update(&data[..5]).unwrap();
+        c.update(&data[5..]).unwrap();
+        assert_eq!(c.total(), 12);
+        #[cfg(feature = "std")]
+        assert_eq!(c.entropy(), crate::slice_entropy(&data));
+        assert_eq!(c.measure(Metric::Shannon), c.entropy());
+        assert!(c.measure(Metric::Min) < c.entropy());
+    }
+
+    #[test]
+    fn static_storage() {
+        // only this test touches it
+        #[allow(static_mut_refs)]
+        let c = unsafe { &mut CALCULATOR };
+        c.update(&[1, 2]).unwrap();
+        assert_eq!(c.entropy(), 1.0);
+        c.clear();
+        assert_eq!(c.entropy(), 0.0);
+    }
+
+    #[test]
+    fn overflow() {
+        let mut c = StackCalculator::new();
+        c.counts[7] = u32::MAX - 1;
+        c.total = (u32::MAX - 1) as u64;
+        let before = c.clone();
+        // there is room for one more 7, but not for two
+        assert!(matches!(c.update(&[7, 1, 7]), Err(Error::Overflow)));
+        assert_eq!(c, before);
+        c.update(&[7, 1, 1]).unwrap();
+        assert_eq!(c.counts()[7], u32::MAX);
+        assert_eq!(c.total(), u32::MAX as u64 + 2);
+    }
+}
@@ -0,0 +1,194 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::chunked::{Chunker, SCRATCH};
+use crate::{Calculator, Error, LastChunk, Metric};
+
+/// Counting never blocks, so every write completes immediately.
+impl AsyncWrite for Calculator {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        input: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(
+            self.get_mut()
+                .hist
+                .update(input)
+                .map(|_| input.len())
+                .map_err(Into::into),
+        )
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// The async counterpart of [crate::ChunkedEntropy]: walks an [AsyncRead] in chunks and yields
+/// `(offset, len, entropy)` for each as a [Stream].
+///
+/// Requires the `async` feature. The entropy of a chunk is calculated on the polling task, which
+/// takes about as long as copying the chunk, so no `spawn_blocking` is needed.
+///
+/// # Example
+///```
+/// use std::future::poll_fn;
+/// use std::pin::Pin;
+/// use futures_core::Stream;
+/// use tokio::io::AsyncWriteExt;
+/// use tropy::AsyncChunkedEntropy;
+///
+/// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+/// let (mut tx, rx) = tokio::io::duplex(64);
+/// tokio::spawn(async move {
+///     tx.write_all(&[0u8, 1, 2, 3, 0, 0, 0, 0]).await.unwrap();
+/// });
+///
+/// let mut chunks = AsyncChunkedEntropy::new(rx, 4).unwrap();
+/// let mut entropies = Vec::new();
+/// while let Some(chunk) = poll_fn(|cx| Pin::new(&mut chunks).poll_next(cx)).await {
+///     entropies.push(chunk.unwrap().2);
+/// }
+/// assert_eq!(entropies, vec![2.0, 0.0]);
+/// # });
+///```
+pub struct AsyncChunkedEntropy<R: AsyncRead + Unpin> {
+    reader: R,
+    chunker: Chunker,
+    scratch: Box<[u8; SCRATCH]>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncChunkedEntropy<R> {
+    /// Walk `reader` in disjoint chunks of `chunksize` bytes, see [crate::ChunkedEntropy::new].
+    ///
+    /// Fails with [Error::ZeroLength] if `chunksize` is zero.
+    pub fn new(reader: R, chunksize: usize) -> Result<Self, Error> {
+        Ok(AsyncChunkedEntropy {
+            reader,
+            chunker: Chunker::new(chunksize)?,
+            scratch: Box::new([0u8; SCRATCH]),
+        })
+    }
+
+    /// Start a chunk every `stride` bytes.
+    ///
+    /// Fails with [Error::ZeroLength] if `stride` is zero.
+    pub fn with_stride(mut self, stride: usize) -> Result<Self, Error> {
+        self.chunker.set_stride(stride)?;
+        Ok(self)
+    }
+
+    /// Set what happens to a chunk cut short by the end of the input.
+    pub fn with_last(mut self, last: LastChunk) -> Self {
+        self.chunker.last = last;
+        self
+    }
+
+    /// Calculate the given measure instead of the Shannon entropy.
+    pub fn with_metric(mut self, metric: Metric) -> Self {
+        self.chunker.metric = metric;
+        self
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for AsyncChunkedEntropy<R> {
+    type Item = Result<(u64, usize, f64), Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if this.chunker.done {
+                return Poll::Ready(None);
+            }
+            let wanted = this.chunker.wanted();
+            if wanted == 0 {
+                return Poll::Ready(this.chunker.emit(false).map(Ok));
+            }
+            let n = wanted.min(SCRATCH);
+            let mut buf = ReadBuf::new(&mut this.scratch[..n]);
+            match Pin::new(&mut this.reader).poll_read(cx, &mut buf) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(e)) => {
+                    this.chunker.done = true;
+                    return Poll::Ready(Some(Err(e.into())));
+                }
+                Poll::Ready(Ok(())) if buf.filled().is_empty() => {
+                    return Poll::Ready(this.chunker.emit(true).map(Ok));
+                }
+                Poll::Ready(Ok(())) => this.chunker.consume(buf.filled()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::future::poll_fn;
+    use std::pin::Pin;
+
+    use futures_core::Stream;
+    use tokio::io::{duplex, AsyncWriteExt};
+
+    use crate::test_util::noise;
+    use crate::{slice_entropy, AsyncChunkedEntropy, Calculator, ChunkedEntropy, LastChunk};
+
+    async fn collect<S>(mut s: S) -> Vec<(u64, usize, f64)>
+    where
+        S: Stream<Item = Result<(u64, usize, f64), crate::Error>> + Unpin,
+    {
+        let mut items = Vec::new();
+        while let Some(item) = poll_fn(|cx| Pin::new(&mut s).poll_next(cx)).await {
+            items.push(item.unwrap());
+        }
+        items
+    }
+
+    #[tokio::test]
+    async fn same_as_blocking() {
+        let data = noise(100_000);
+        for &(chunksize, stride) in &[(1024, 1024), (1000, 300), (64, 5000), (7, 7)] {
+            // a small pipe forces many partial reads and pending polls
+            let (mut tx, rx) = duplex(100);
+            let input = data.clone();
+            let writer = tokio::spawn(async move { tx.write_all(&input).await.unwrap() });
+
+            let stream = AsyncChunkedEntropy::new(rx, chunksize)
+                .unwrap()
+                .with_stride(stride)
+                .unwrap()
+                .with_last(LastChunk::Include);
+            let expected: Vec<(u64, usize, f64)> = ChunkedEntropy::new(&data[..], chunksize)
+                .unwrap()
+                .with_stride(stride)
+                .unwrap()
+                .with_last(LastChunk::Include)
+                .map(|r| r.unwrap())
+                .collect();
+
+            assert_eq!(collect(stream).await, expected);
+            writer.await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn async_write() {
+        let data = noise(100_000);
+        let (mut tx, mut rx) = duplex(100);
+        let input = data.clone();
+        let writer = tokio::spawn(async move { tx.write_all(&input).await.unwrap() });
+
+        let mut c = Calculator::new();
+        tokio::io::copy(&mut rx, &mut c).await.unwrap();
+        writer.await.unwrap();
+        assert_eq!(c.entropy(), slice_entropy(&data));
+    }
+}
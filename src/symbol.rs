@@ -0,0 +1,361 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::io::Write;
+use std::str::FromStr;
+
+use crate::{Error, Metric};
+
+/// Byte order of symbols wider than a byte
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+/// The unit the input is split into before counting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Symbol {
+    /// Single bits, most significant first
+    Bit,
+    /// Half bytes, high nibble first
+    Nibble,
+    /// Bytes, like [crate::Calculator]
+    #[default]
+    Byte,
+    /// 16 bit words
+    U16(Endian),
+    /// 32 bit words
+    U32(Endian),
+}
+
+impl Symbol {
+    /// The width of a symbol in bits, which is also its maximum entropy.
+    pub fn bits(&self) -> u32 {
+        match self {
+            Symbol::Bit => 1,
+            Symbol::Nibble => 4,
+            Symbol::Byte => 8,
+            Symbol::U16(_) => 16,
+            Symbol::U32(_) => 32,
+        }
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name = match self {
+            Symbol::Bit => "bit",
+            Symbol::Nibble => "nibble",
+            Symbol::Byte => "byte",
+            Symbol::U16(Endian::Little) => "u16le",
+            Symbol::U16(Endian::Big) => "u16be",
+            Symbol::U32(Endian::Little) => "u32le",
+            Symbol::U32(Endian::Big) => "u32be",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Symbol {
+    type Err = Error;
+
+    /// Parses `bit`, `nibble`, `byte`, `u16le`, `u16be`, `u32le` or `u32be`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bit" => Ok(Symbol::Bit),
+            "nibble" => Ok(Symbol::Nibble),
+            "byte" => Ok(Symbol::Byte),
+            "u16le" => Ok(Symbol::U16(Endian::Little)),
+            "u16be" => Ok(Symbol::U16(Endian::Big)),
+            "u32le" => Ok(Symbol::U32(Endian::Little)),
+            "u32be" => Ok(Symbol::U32(Endian::Big)),
+            _ => Err(Error::UnsupportedFormat(format!("Unknown symbol {:?}", s))),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Counts {
+    // up to 16 bit symbols, indexed by the symbol
+    Dense(Vec<u64>),
+    // 32 bit symbols would need 32GiB dense
+    Sparse(HashMap<u32, u64>),
+}
+
+/// An entropy calculator over symbols of a configurable width.
+///
+/// Bytes which don't make up a whole wide symbol yet are kept until the next update.
+///
+/// # Example
+///```
+/// use tropy::{Endian, Symbol, SymbolCalculator};
+///
+/// // UTF-16 text: every other byte is zero
+/// let text: Vec<u8> = "tropy".encode_utf16().flat_map(|c| c.to_le_bytes().to_vec()).collect();
+///
+/// let mut bytes = SymbolCalculator::new(Symbol::Byte);
+/// bytes.update(&text);
+/// let mut words = SymbolCalculator::new(Symbol::U16(Endian::Little));
+/// words.update(&text);
+///
+/// // five distinct words, but the zeros dominate the bytes
+/// assert_eq!(words.entropy(), 5f64.log2());
+/// assert!(bytes.entropy() < words.entropy());
+///```
+#[derive(Debug, Clone)]
+pub struct SymbolCalculator {
+    symbol: Symbol,
+    counts: Counts,
+    total: u64,
+    // bytes of an incomplete wide symbol
+    pending: [u8; 4],
+    npending: usize,
+}
+
+impl SymbolCalculator {
+    /// Instantiate a new calculator counting the given symbols.
+    pub fn new(symbol: Symbol) -> Self {
+        let counts = match symbol {
+            Symbol::U32(_) => Counts::Sparse(HashMap::new()),
+            _ => Counts::Dense(vec![0u64; 1 << symbol.bits()]),
+        };
+        SymbolCalculator::with_counts(symbol, counts)
+    }
+
+    /// Instantiate a new calculator for about `bytes` bytes of input.
+    ///
+    /// If they hold fewer symbols than there are distinct ones, e.g. a chunk of 1 KiB and 16 bit
+    /// symbols, only the symbols seen are stored and visited by [SymbolCalculator::measure].
+    pub fn with_capacity(symbol: Symbol, bytes: usize) -> Self {
+        let symbols = bytes.saturating_mul(8) / symbol.bits() as usize;
+        match symbol {
+            Symbol::U16(_) if symbols < 1 << 16 => SymbolCalculator::with_counts(
+                symbol,
+                Counts::Sparse(HashMap::with_capacity(symbols)),
+            ),
+            _ => SymbolCalculator::new(symbol),
+        }
+    }
+
+    fn with_counts(symbol: Symbol, counts: Counts) -> Self {
+        SymbolCalculator {
+            symbol,
+            counts,
+            total: 0,
+            pending: [0u8; 4],
+            npending: 0,
+        }
+    }
+
+    /// The symbols counted by this calculator.
+    pub fn symbol(&self) -> Symbol {
+        self.symbol
+    }
+
+    /// The number of symbols counted.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// The number of times the given symbol was counted.
+    pub fn count(&self, symbol: u32) -> u64 {
+        match &self.counts {
+            Counts::Dense(c) => c.get(symbol as usize).cloned().unwrap_or(0),
+            Counts::Sparse(c) => c.get(&symbol).cloned().unwrap_or(0),
+        }
+    }
+
+    /// Split the given bytes into symbols and count them.
+    pub fn update(&mut self, input: &[u8]) {
+        match self.symbol {
+            Symbol::Bit => input.iter().for_each(|b| {
+                let ones = b.count_ones() as u64;
+                self.add(1, ones);
+                self.add(0, 8 - ones);
+            }),
+            Symbol::Nibble => input.iter().for_each(|b| {
+                self.add((b >> 4) as u32, 1);
+                self.add((b & 0xf) as u32, 1);
+            }),
+            Symbol::Byte => input.iter().for_each(|b| self.add(*b as u32, 1)),
+            Symbol::U16(_) | Symbol::U32(_) => {
+                let width = (self.symbol.bits() / 8) as usize;
+                for b in input {
+                    self.pending[self.npending] = *b;
+                    self.npending += 1;
+                    if self.npending == width {
+                        let s = self.word();
+                        self.add(s, 1);
+                        self.npending = 0;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Calculate the Shannon entropy in bits per symbol over the symbols counted so far, i.e.
+    /// between 0 and [Symbol::bits].
+    ///
+    /// An incomplete wide symbol is kept for the next update.
+    pub fn entropy(&self) -> f64 {
+        self.measure(Metric::Shannon)
+    }
+
+    /// Calculate the Shannon entropy scaled to bits per byte (0 to 8), so it can be compared
+    /// across symbol widths.
+    pub fn entropy_per_byte(&self) -> f64 {
+        self.entropy() * 8.0 / self.symbol.bits() as f64
+    }
+
+    /// Calculate the given metric in bits per symbol over the symbols counted so far.
+    pub fn measure(&self, metric: Metric) -> f64 {
+        match &self.counts {
+            Counts::Dense(c) => metric.of_counts(c.iter().cloned(), self.total),
+            Counts::Sparse(c) => metric.of_counts(c.values().cloned(), self.total),
+        }
+    }
+
+    /// Drop all counts and any incomplete symbol.
+    pub fn clear(&mut self) {
+        match &mut self.counts {
+            Counts::Dense(c) => c.iter_mut().for_each(|n| *n = 0),
+            Counts::Sparse(c) => c.clear(),
+        }
+        self.total = 0;
+        self.npending = 0;
+    }
+
+    fn add(&mut self, symbol: u32, n: u64) {
+        match &mut self.counts {
+            Counts::Dense(c) => c[symbol as usize] += n,
+            Counts::Sparse(c) => *c.entry(symbol).or_insert(0) += n,
+        }
+        self.total += n;
+    }
+
+    fn word(&self) -> u32 {
+        let p = &self.pending;
+        match self.symbol {
+            Symbol::U16(Endian::Little) => u16::from_le_bytes([p[0], p[1]]) as u32,
+            Symbol::U16(Endian::Big) => u16::from_be_bytes([p[0], p[1]]) as u32,
+            Symbol::U32(Endian::Little) => u32::from_le_bytes(*p),
+            Symbol::U32(Endian::Big) => u32::from_be_bytes(*p),
+            _ => unreachable!("only wide symbols are assembled from bytes"),
+        }
+    }
+}
+
+impl Write for SymbolCalculator {
+    fn write(&mut self, input: &[u8]) -> io::Result<usize> {
+        self.update(input);
+        Ok(input.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{slice_entropy, Endian, Symbol, SymbolCalculator};
+
+    fn entropy(symbol: Symbol, input: &[u8]) -> f64 {
+        let mut c = SymbolCalculator::new(symbol);
+        c.update(input);
+        c.entropy()
+    }
+
+    #[test]
+    fn widths() {
+        // 0x0f has as many zero as one bits and two distinct nibbles
+        assert_eq!(entropy(Symbol::Bit, &[0x0f; 16]), 1.0);
+        assert_eq!(entropy(Symbol::Nibble, &[0x0f; 16]), 1.0);
+        assert_eq!(entropy(Symbol::Byte, &[0x0f; 16]), 0.0);
+        assert_eq!(entropy(Symbol::Bit, &[0xff; 16]), 0.0);
+
+        let data: Vec<u8> = (0u32..3000).map(|i| (i * 7 % 256) as u8).collect();
+        assert!((entropy(Symbol::Byte, &data) - slice_entropy(&data)).abs() < 1e-12);
+
+        // the same four byte word over and over
+        let words: Vec<u8> = [1u8, 2, 3, 4].iter().cycle().take(400).cloned().collect();
+        assert_eq!(entropy(Symbol::U32(Endian::Big), &words), 0.0);
+        assert_eq!(entropy(Symbol::U16(Endian::Little), &words), 1.0);
+        assert_eq!(entropy(Symbol::Byte, &words), 2.0);
+    }
+
+    #[test]
+    fn endianness() {
+        let mut le = SymbolCalculator::new(Symbol::U16(Endian::Little));
+        let mut be = SymbolCalculator::new(Symbol::U16(Endian::Big));
+        le.update(&[0x01, 0x02, 0x01, 0x02, 0x03, 0x04]);
+        be.update(&[0x01, 0x02, 0x01, 0x02, 0x03, 0x04]);
+        assert_eq!((le.count(0x0201), le.count(0x0102)), (2, 0));
+        assert_eq!((be.count(0x0102), be.count(0x0201)), (2, 0));
+        assert_eq!((le.count(0x0403), be.count(0x0304)), (1, 1));
+        assert_eq!(le.total(), 3);
+
+        let mut le = SymbolCalculator::new(Symbol::U32(Endian::Little));
+        let mut be = SymbolCalculator::new(Symbol::U32(Endian::Big));
+        le.update(&[0x01, 0x02, 0x03, 0x04]);
+        be.update(&[0x01, 0x02, 0x03, 0x04]);
+        assert_eq!((le.count(0x0403_0201), le.count(0x0102_0304)), (1, 0));
+        assert_eq!((be.count(0x0102_0304), be.count(0x0403_0201)), (1, 0));
+    }
+
+    #[test]
+    fn sparse() {
+        let data: Vec<u8> = (0u32..1024).map(|i| (i * 37 % 251) as u8).collect();
+        for symbol in [
+            Symbol::Nibble,
+            Symbol::U16(Endian::Big),
+            Symbol::U32(Endian::Big),
+        ] {
+            let mut dense = SymbolCalculator::new(symbol);
+            let mut sized = SymbolCalculator::with_capacity(symbol, data.len());
+            dense.update(&data);
+            sized.update(&data);
+            assert_eq!(sized.total(), dense.total());
+            assert_eq!(sized.count(0x2a4f), dense.count(0x2a4f));
+            // the sparse counts are summed in a different order
+            assert!((sized.entropy() - dense.entropy()).abs() < 1e-12);
+
+            // clearing keeps the counts sparse
+            sized.clear();
+            sized.update(&data);
+            assert!((sized.entropy() - dense.entropy()).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn partial_symbols() {
+        let mut c = SymbolCalculator::new(Symbol::U32(Endian::Little));
+        c.update(&[1, 2, 3]);
+        assert_eq!(c.total(), 0);
+        c.update(&[4, 1, 2, 3, 4, 9, 9]);
+        assert_eq!(c.total(), 2);
+        assert_eq!(c.entropy(), 0.0);
+
+        c.clear();
+        c.update(&[0u8; 3]);
+        assert_eq!(c.total(), 0);
+    }
+
+    #[test]
+    fn per_byte() {
+        let mut c = SymbolCalculator::new(Symbol::Bit);
+        c.update(&[0x55; 8]);
+        assert_eq!(c.entropy(), 1.0);
+        assert_eq!(c.entropy_per_byte(), 8.0);
+    }
+
+    #[test]
+    fn parse() {
+        for s in &["bit", "nibble", "byte", "u16le", "u16be", "u32le", "u32be"] {
+            assert_eq!(&s.parse::<Symbol>().unwrap().to_string(), s);
+        }
+        assert!("u64le".parse::<Symbol>().is_err());
+    }
+}
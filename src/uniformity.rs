@@ -0,0 +1,201 @@
+use std::f64::consts::LN_2;
+
+use crate::ent::chi_square_p_value;
+use crate::{Error, Estimator, Histogram};
+
+/// Tests histograms against uniformly random bytes and puts a confidence interval on their entropy.
+///
+/// The test is the G-test against 256 equally likely values. Its statistic
+/// `G = 2 n ln 2 (8 - H)` grows with the entropy missing to 8 bits, so it asks the same question
+/// as the entropy, but scaled by the number of bytes `n`: 7.93 bits is unremarkable for 4KiB of
+/// random bytes and far too low for 1MiB. `G` is approximately chi-square distributed with 255
+/// degrees of freedom, which holds from about 5 bytes per value, i.e. chunks of 1280 bytes; for
+/// smaller chunks the p-value is only indicative.
+///
+/// The interval is centred on the Miller-Madow estimate, with the variance of the plug-in
+/// estimate to second order (Harris 1975). It is about the entropy of the source of the bytes,
+/// not the entropy of the sample, and like the estimate it is biased low for chunks which can't
+/// show most of the 256 values.
+///
+/// # Example
+///```
+/// use tropy::{Histogram, UniformityTest};
+///
+/// let test = UniformityTest::new(0.01).unwrap();
+/// let counter: Vec<u8> = (0..4096).map(|i| i as u8).collect();
+/// let u = test.of(&Histogram::from(&counter[..]));
+/// // a counter has a perfectly flat histogram
+/// assert!(u.random);
+/// assert!(u.lower > 7.98 && u.upper == 8.0);
+///
+/// let text = b"Lorem ipsum dolor sit amet, consectetur adipiscing elit. ".repeat(20);
+/// assert!(!test.of(&Histogram::from(&text[..])).random);
+///```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UniformityTest {
+    alpha: f64,
+    // the two-sided quantile of the standard normal distribution for `1 - alpha`
+    z: f64,
+}
+
+/// The result of a [UniformityTest] of one histogram.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Uniformity {
+    /// The Shannon entropy in bits per byte, see [Histogram::entropy]
+    pub entropy: f64,
+    /// The lower bound of the confidence interval of the entropy of the source
+    pub lower: f64,
+    /// The upper bound of the confidence interval, at most 8
+    pub upper: f64,
+    /// The G statistic
+    pub g: f64,
+    /// The probability of a G at least this large for truly random bytes
+    pub p_value: f64,
+    /// Whether the bytes are indistinguishable from random ones, i.e. the p-value is at least the
+    /// significance level
+    pub random: bool,
+}
+
+impl UniformityTest {
+    /// A test at the significance level `alpha`, giving intervals at the confidence level
+    /// `1 - alpha`.
+    ///
+    /// Fails with [Error::InvalidArgument] unless `alpha` is strictly between 0 and 1.
+    pub fn new(alpha: f64) -> Result<Self, Error> {
+        if !(alpha > 0.0 && alpha < 1.0) {
+            return Err(Error::InvalidArgument(format!(
+                "The significance level must be between 0 and 1, not {}",
+                alpha
+            )));
+        }
+        // P(|Z| > z) is the upper tail of chi-square with 1 degree of freedom at z^2
+        let (mut low, mut high) = (0.0f64, 40.0f64);
+        for _ in 0..100 {
+            let mid = (low + high) / 2.0;
+            if chi_square_p_value(mid * mid, 1.0) > alpha {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        Ok(UniformityTest {
+            alpha,
+            z: (low + high) / 2.0,
+        })
+    }
+
+    /// The significance level of the test.
+    pub fn alpha(&self) -> f64 {
+        self.alpha
+    }
+
+    /// Test the bytes counted by a histogram.
+    ///
+    /// An empty histogram has an entropy and interval of 0 and isn't flagged as random.
+    pub fn of(&self, h: &Histogram) -> Uniformity {
+        let entropy = h.entropy();
+        if h.is_empty() {
+            return Uniformity {
+                entropy,
+                lower: 0.0,
+                upper: 0.0,
+                g: 0.0,
+                p_value: 1.0,
+                random: false,
+            };
+        }
+        let n = h.total() as f64;
+        let g = (2.0 * n * LN_2 * (8.0 - entropy)).max(0.0);
+        let p_value = chi_square_p_value(g, 255.0);
+
+        let seen = h.counts().iter().filter(|c| **c > 0).count() as f64;
+        let second_moment: f64 = h
+            .counts()
+            .iter()
+            .filter(|c| **c > 0)
+            .map(|c| {
+                let p = *c as f64 / n;
+                p * p.log2() * p.log2()
+            })
+            .sum();
+        let variance = (second_moment - entropy * entropy).max(0.0) / n
+            + (seen - 1.0) / (2.0 * n * n * LN_2 * LN_2);
+        let centre = Estimator::MillerMadow.of(h);
+        let margin = self.z * variance.sqrt();
+
+        Uniformity {
+            entropy,
+            lower: (centre - margin).clamp(0.0, 8.0),
+            upper: (centre + margin).clamp(0.0, 8.0),
+            g,
+            p_value,
+            random: p_value >= self.alpha,
+        }
+    }
+}
+
+impl Default for UniformityTest {
+    /// A test at the significance level 0.01
+    fn default() -> Self {
+        UniformityTest::new(0.01).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_util::seeded_noise;
+    use crate::{Histogram, UniformityTest};
+
+    #[test]
+    fn quantile() {
+        let z = |alpha| UniformityTest::new(alpha).unwrap().z;
+        assert!((z(0.05) - 1.959964).abs() < 1e-5);
+        assert!((z(0.01) - 2.575829).abs() < 1e-5);
+        for alpha in [0.0, 1.0, -0.5, f64::NAN].iter() {
+            assert!(UniformityTest::new(*alpha).is_err());
+        }
+    }
+
+    #[test]
+    fn random() {
+        let test = UniformityTest::new(0.05).unwrap();
+        // about 5% of random chunks fail at this level, and the interval covers 8 bits about
+        // as often
+        let chunks = 400;
+        let mut flagged = 0;
+        let mut covered = 0;
+        for seed in 1..=chunks {
+            let u = test.of(&Histogram::from(&seeded_noise(4096, seed)[..]));
+            assert!(u.lower <= u.upper && u.upper <= 8.0);
+            flagged += u.random as usize;
+            covered += (u.upper == 8.0) as usize;
+        }
+        assert!(flagged > chunks as usize * 90 / 100, "{}", flagged);
+        assert!(covered > chunks as usize * 85 / 100, "{}", covered);
+    }
+
+    #[test]
+    fn structured() {
+        let test = UniformityTest::default();
+        let constant = test.of(&Histogram::from(&[0u8; 4096][..]));
+        assert!(!constant.random);
+        assert_eq!((constant.lower, constant.upper), (0.0, 0.0));
+        assert!(constant.p_value < 1e-12);
+
+        // 7.9 bits look random but aren't for 64KiB: half the values are 50% more likely
+        let skewed: Vec<u8> = seeded_noise(1 << 16, 7)
+            .iter()
+            .enumerate()
+            .map(|(i, b)| if i % 5 == 0 { b & 0x7f } else { *b })
+            .collect();
+        let u = test.of(&Histogram::from(&skewed[..]));
+        assert!(u.entropy > 7.9);
+        assert!(!u.random);
+        assert!(u.upper < 8.0);
+
+        let empty = test.of(&Histogram::new());
+        assert!(!empty.random);
+        assert_eq!(empty.upper, 0.0);
+    }
+}